@@ -11,12 +11,18 @@ use std::{
     thread,
 };
 
-use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
 use crossbeam_channel::bounded;
-use flate2::read::GzDecoder;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use orcid_data_toolkit::{
+    convert_tgz, index_tgz, record, ConvertFormat, ConvertOptions, IndexOptions, NameConverter,
+};
 use quick_xml::de::Deserializer;
 use rayon::prelude::*;
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use tar::Archive;
 
 // Minimal structs for XML parsing benchmark (mirrors lib.rs)
@@ -103,7 +109,45 @@ fn find_test_tgz() -> Option<std::path::PathBuf> {
             return Some(path);
         }
     }
-    None
+    generate_test_tgz()
+}
+
+/// Build an archive of copies of the XML test fixtures (~5MB, ~110MB
+/// uncompressed), for when none of the above is available.
+fn generate_test_tgz() -> Option<std::path::PathBuf> {
+    const COPIES: usize = 40;
+    let path = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("bench-generated.tar.gz");
+    if path.exists() {
+        return Some(path);
+    }
+    let fixtures = [
+        "alex",
+        "duplicate-orgs",
+        "jose",
+        "lars",
+        "no-family-name",
+        "paolo",
+    ];
+    let tmp_path = path.with_extension("gz.tmp");
+    let encoder = GzEncoder::new(File::create(&tmp_path).ok()?, Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    for copy in 0..COPIES {
+        for fixture in fixtures {
+            let xml = std::fs::read_to_string(format!("tests/data/{fixture}.xml")).ok()?;
+            // Members are named by the ORCiD iD of their record
+            let orcid = xml.split("<common:path>").nth(1)?.get(..19)?;
+            let member = format!("ORCID_summaries/{copy:03}/{orcid}.xml");
+            let mut header = tar::Header::new_gnu();
+            header.set_size(xml.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, member, xml.as_bytes())
+                .ok()?;
+        }
+    }
+    builder.into_inner().ok()?.finish().ok()?;
+    std::fs::rename(&tmp_path, &path).ok()?;
+    Some(path)
 }
 
 /// Benchmark gzip decompression only.
@@ -276,10 +320,7 @@ fn bench_xml_parsing_parallel(c: &mut Criterion) {
         &xml_contents,
         |b, contents| {
             b.iter(|| {
-                let parsed: usize = contents
-                    .par_iter()
-                    .filter_map(|xml| parse_xml(xml))
-                    .count();
+                let parsed: usize = contents.par_iter().filter_map(|xml| parse_xml(xml)).count();
                 black_box(parsed)
             });
         },
@@ -325,10 +366,10 @@ fn bench_full_pipeline_parallel(c: &mut Criterion) {
 
                         if entry_path.extension().and_then(OsStr::to_str) == Some("xml") {
                             let mut content = String::new();
-                            if entry.read_to_string(&mut content).is_ok() {
-                                if tx.send(content).is_err() {
-                                    break;
-                                }
+                            if entry.read_to_string(&mut content).is_ok()
+                                && tx.send(content).is_err()
+                            {
+                                break;
                             }
                         }
                     }
@@ -383,10 +424,10 @@ fn bench_full_pipeline_sequential(c: &mut Criterion) {
 
                     if entry_path.extension().and_then(OsStr::to_str) == Some("xml") {
                         let mut content = String::new();
-                        if entry.read_to_string(&mut content).is_ok() {
-                            if parse_xml(&content).is_some() {
-                                parsed += 1;
-                            }
+                        if entry.read_to_string(&mut content).is_ok()
+                            && parse_xml(&content).is_some()
+                        {
+                            parsed += 1;
                         }
                     }
                 }
@@ -398,6 +439,120 @@ fn bench_full_pipeline_sequential(c: &mut Criterion) {
     group.finish();
 }
 
+/// Benchmark the actual conversion, with sequential and (using a checkpoint
+/// index) parallel decompression.
+fn bench_full_pipeline_indexed(c: &mut Criterion) {
+    let Some(tgz_path) = find_test_tgz() else {
+        eprintln!("No test TGZ file found. Skipping indexed pipeline benchmark.");
+        return;
+    };
+
+    let tmp_dir = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    let mut index_name = tgz_path.file_name().unwrap().to_owned();
+    index_name.push(".idx");
+    let index_file = tmp_dir.join(index_name);
+    let output_file = tmp_dir.join("bench-output.csv");
+    let index_options = IndexOptions {
+        span: 1,
+        sort_memory: 64,
+        tmp_dir: None,
+    };
+    index_tgz(&tgz_path, &index_file, &index_options).unwrap();
+
+    let file_size = std::fs::metadata(&tgz_path).unwrap().len();
+    let mut group = c.benchmark_group("full_pipeline");
+    group.throughput(Throughput::Bytes(file_size));
+    group.sample_size(10);
+
+    let size = format!("{:.0}MB", file_size as f64 / 1024.0 / 1024.0);
+    for (name, index) in [("convert", None), ("convert_indexed", Some(index_file))] {
+        group.bench_with_input(BenchmarkId::new(name, &size), &index, |b, index| {
+            b.iter(|| {
+                convert_tgz(
+                    &tgz_path,
+                    &output_file,
+                    &None,
+                    &None,
+                    &ConvertFormat::InvenioRDMNames,
+                    &ConvertOptions::default(),
+                    index,
+                )
+                .unwrap()
+            });
+        });
+    }
+
+    group.finish();
+}
+
+/// Benchmark the per-record cost of parsing and converting the (large) XML
+/// fixtures in memory, e.g. `paolo.xml` is mostly made up of works.
+fn bench_record_conversion(c: &mut Criterion) {
+    let converter = NameConverter::new(&ConvertOptions::default()).unwrap();
+    let mut group = c.benchmark_group("record_conversion");
+    group.throughput(Throughput::Elements(1));
+
+    for fixture in ["alex", "duplicate-orgs", "paolo"] {
+        let xml = std::fs::read_to_string(format!("tests/data/{fixture}.xml")).unwrap();
+        let parameter = format!("{}_{:.0}KB", fixture, xml.len() as f64 / 1024.0);
+        group.bench_with_input(BenchmarkId::new("parse", &parameter), &xml, |b, xml| {
+            b.iter(|| black_box(record::Record::from_xml(xml).unwrap()));
+        });
+        group.bench_with_input(
+            BenchmarkId::new("parse_in_place", &parameter),
+            &xml,
+            |b, xml| {
+                b.iter_batched_ref(
+                    || xml.clone(),
+                    |xml| {
+                        black_box(record::Record::from_xml_mut(xml).unwrap());
+                    },
+                    BatchSize::LargeInput,
+                );
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("convert", &parameter), &xml, |b, xml| {
+            b.iter_batched_ref(
+                || xml.clone(),
+                |xml| {
+                    let record = record::Record::from_xml_mut(xml).unwrap();
+                    black_box(converter.convert(&record).unwrap())
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+
+    group.finish();
+}
+
+/// Benchmark SHA-256, which conversions hash every record's ORCiD iD with (for
+/// its `id` and hash shard) and every byte of sharded output.
+fn bench_sha256(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sha256");
+    let orcid = "0000-0002-5082-6404";
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("orcid", |b| {
+        b.iter(|| {
+            let mut hasher = Sha256::new();
+            hasher.update(black_box(orcid.as_bytes()));
+            hasher.finalize()
+        });
+    });
+
+    let data = vec![b'a'; 1024 * 1024];
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("1MB", |b| {
+        b.iter(|| {
+            let mut hasher = Sha256::new();
+            hasher.update(black_box(&data));
+            hasher.finalize()
+        });
+    });
+
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_gzip_decompression,
@@ -406,5 +561,8 @@ criterion_group!(
     bench_xml_parsing_parallel,
     bench_full_pipeline_sequential,
     bench_full_pipeline_parallel,
+    bench_full_pipeline_indexed,
+    bench_record_conversion,
+    bench_sha256,
 );
 criterion_main!(benches);
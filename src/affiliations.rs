@@ -0,0 +1,86 @@
+//! Selection of records by their (ROR-resolved) affiliations.
+
+use clap::Args;
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct AffiliationFilter {
+    /// Only keep people affiliated with the given ROR ID (e.g. "01ggx4157" or
+    /// "https://ror.org/01ggx4157"). Can be repeated.
+    #[arg(long = "affiliation-ror", value_name = "ROR_ID", value_parser = parse_ror_id)]
+    pub ror_ids: Vec<String>,
+
+    /// Only keep people affiliated with an organization in the given country
+    /// (ISO 3166-1 alpha-2 code, e.g. "CH"). Can be repeated.
+    #[arg(long = "affiliation-country", value_name = "ISO", value_parser = parse_country)]
+    pub countries: Vec<String>,
+
+    /// Only keep people with at least one affiliation
+    #[arg(long = "any-affiliation", conflicts_with = "no_affiliation")]
+    pub any_affiliation: bool,
+
+    /// Only keep people without any affiliation
+    #[arg(long = "no-affiliation")]
+    pub no_affiliation: bool,
+
+    /// Also match past employments against the affiliation filters (e.g. for
+    /// alumni exports). Only current employments are output as affiliations.
+    #[arg(long = "include-past-employments")]
+    pub include_past: bool,
+}
+
+pub(crate) fn parse_ror_id(value: &str) -> Result<String, String> {
+    let id = value.trim().trim_end_matches('/');
+    let id = id.rsplit_once('/').map_or(id, |(_, id)| id);
+    if id.len() != 9 || !id.starts_with('0') || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(format!("Invalid ROR ID {:?}", value));
+    }
+    Ok(id.to_lowercase())
+}
+
+pub(crate) fn parse_country(value: &str) -> Result<String, String> {
+    if value.len() != 2 || !value.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(format!(
+            "Invalid ISO 3166-1 alpha-2 country code {:?}",
+            value
+        ));
+    }
+    Ok(value.to_uppercase())
+}
+
+/// An employment, as seen by the affiliation filter
+pub(crate) struct Affiliated<'a> {
+    pub ror_id: Option<String>,
+    pub country: Option<&'a str>,
+    pub current: bool,
+}
+
+impl AffiliationFilter {
+    pub(crate) fn is_match(&self, employments: &[Affiliated]) -> bool {
+        let matched: Vec<&Affiliated> = employments
+            .iter()
+            .filter(|e| e.current || self.include_past)
+            .collect();
+        if self.any_affiliation && matched.is_empty() {
+            return false;
+        }
+        if self.no_affiliation && !matched.is_empty() {
+            return false;
+        }
+        let has_ror_id = |e: &Affiliated| {
+            e.ror_id
+                .as_deref()
+                .is_some_and(|id| self.ror_ids.iter().any(|r| r.eq_ignore_ascii_case(id)))
+        };
+        if !self.ror_ids.is_empty() && !matched.iter().any(|e| has_ror_id(e)) {
+            return false;
+        }
+        let has_country = |e: &Affiliated| {
+            e.country
+                .is_some_and(|c| self.countries.iter().any(|i| i.eq_ignore_ascii_case(c)))
+        };
+        if !self.countries.is_empty() && !matched.iter().any(|e| has_country(e)) {
+            return false;
+        }
+        true
+    }
+}
@@ -0,0 +1,122 @@
+//! Output files that are written atomically.
+//!
+//! Output is written to a temporary file next to the output file (e.g.
+//! `names.csv.tmp`), which replaces it once complete, so that a failed run
+//! never leaves a truncated output file behind.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+
+use crate::error::ErrorContext;
+
+/// A temporary file that replaces its output file once committed
+pub(crate) struct AtomicFile {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    file: File,
+    /// Whether to keep the temporary file when not committed
+    keep: bool,
+    committed: bool,
+}
+
+impl AtomicFile {
+    /// Path of the temporary file of `path`
+    pub(crate) fn tmp_path(path: &Path) -> PathBuf {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        PathBuf::from(tmp_path)
+    }
+
+    /// The output file and its temporary file, for error messages
+    fn describe(path: &Path, tmp_path: &Path) -> String {
+        format!("{} (temporary file {})", path.display(), tmp_path.display())
+    }
+
+    /// Create the temporary file of `path`, replacing any previous one
+    pub(crate) fn create(path: &Path) -> Result<Self> {
+        let tmp_path = Self::tmp_path(path);
+        let file = File::create(&tmp_path)
+            .output_context(|| format!("Error opening file {}", Self::describe(path, &tmp_path)))?;
+        Ok(AtomicFile {
+            path: path.to_path_buf(),
+            tmp_path,
+            file,
+            keep: false,
+            committed: false,
+        })
+    }
+
+    /// Reopen the temporary file of an interrupted run to continue writing at
+    /// `offset`, truncating anything written after its checkpoint
+    pub(crate) fn reopen(path: &Path, offset: u64) -> Result<Self> {
+        let tmp_path = Self::tmp_path(path);
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(&tmp_path)
+            .output_context(|| format!("Error opening file {}", Self::describe(path, &tmp_path)))?;
+        let len = file.metadata()?.len();
+        if len < offset {
+            bail!(
+                "File {} is shorter than at its checkpoint ({} < {} bytes)",
+                Self::describe(path, &tmp_path),
+                len,
+                offset
+            );
+        }
+        file.set_len(offset).output_context(|| {
+            format!("Error truncating file {}", Self::describe(path, &tmp_path))
+        })?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(AtomicFile {
+            path: path.to_path_buf(),
+            tmp_path,
+            file,
+            keep: true,
+            committed: false,
+        })
+    }
+
+    /// Keep the temporary file if the run fails, e.g. to resume it from a
+    /// checkpoint
+    pub(crate) fn keep_on_error(mut self) -> Self {
+        self.keep = true;
+        self
+    }
+
+    pub(crate) fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Path of the output file
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Replace the output file with the temporary file. Everything written to
+    /// it must be flushed beforehand.
+    pub(crate) fn commit(mut self) -> Result<()> {
+        self.file.sync_all().output_context(|| {
+            format!(
+                "Error writing file {}",
+                Self::describe(&self.path, &self.tmp_path)
+            )
+        })?;
+        fs::rename(&self.tmp_path, &self.path)
+            .output_context(|| format!("Error writing file {}", self.path.display()))?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed && !self.keep {
+            let _ = fs::remove_file(&self.tmp_path);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Checkpoints of long-running conversions, to resume them after interruptions.
+//!
+//! A checkpoint records the last member of the archive whose records were
+//! fully written, together with the size of the output files at that point.
+//! It is stored next to the output file (e.g. `names.csv.checkpoint`) and
+//! removed once the conversion completes.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+
+use crate::atomic_file::AtomicFile;
+use crate::error::ErrorContext;
+use clap::Args;
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct CheckpointOptions {
+    /// Periodically write a checkpoint next to the output file (e.g.
+    /// `names.csv.checkpoint`), every given number of seconds
+    #[arg(long = "checkpoint-interval", value_name = "SECONDS")]
+    pub interval: Option<u64>,
+
+    /// Resume an interrupted conversion from its checkpoint, skipping the
+    /// members of the archive that were already converted
+    #[arg(long = "resume")]
+    pub resume: bool,
+}
+
+/// Default interval between checkpoints of resumed conversions
+const DEFAULT_INTERVAL: u64 = 60;
+
+impl CheckpointOptions {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.interval.is_some() || self.resume
+    }
+}
+
+/// A member of the archive
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub(crate) struct Member {
+    pub path: String,
+    /// Position of its header in the decompressed archive
+    pub position: u64,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub(crate) struct Checkpoint {
+    pub input_file: PathBuf,
+    pub input_size: u64,
+    /// Output format, e.g. "invenio-rdm-names"
+    pub format: String,
+    /// Fingerprint of the conversion options (see `NameConverter::fingerprint`)
+    pub options_fingerprint: String,
+    /// Creation timestamp of the converted records, kept when resuming
+    pub created: String,
+    /// Last member whose records were fully written
+    pub last_member: Member,
+    pub output_offset: u64,
+    pub review_offset: Option<u64>,
+    pub redirects_offset: Option<u64>,
+}
+
+impl Checkpoint {
+    /// Path of the checkpoint of a conversion to `output_file`
+    pub(crate) fn path(output_file: &Path) -> PathBuf {
+        let mut path = output_file.as_os_str().to_owned();
+        path.push(".checkpoint");
+        PathBuf::from(path)
+    }
+
+    /// Load the checkpoint at `path`, checking that it is for `input_file`
+    /// and a conversion with the same format and options
+    pub(crate) fn load(
+        path: &Path,
+        input_file: &Path,
+        format: &str,
+        options_fingerprint: &str,
+    ) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .input_context(|| format!("Error reading checkpoint {}", path.display()))?;
+        let checkpoint: Checkpoint = serde_json::from_str(&content)
+            .parse_context(|| format!("Invalid checkpoint {}", path.display()))?;
+        let input_size = fs::metadata(input_file)
+            .input_context(|| format!("Error opening file {}", input_file.display()))?
+            .len();
+        if input_size != checkpoint.input_size {
+            bail!(
+                "Checkpoint {} is for a different input file ({})",
+                path.display(),
+                checkpoint.input_file.display()
+            );
+        }
+        if format != checkpoint.format {
+            bail!(
+                "Checkpoint {} is for a different output format ({})",
+                path.display(),
+                checkpoint.format
+            );
+        }
+        if options_fingerprint != checkpoint.options_fingerprint {
+            bail!(
+                "Checkpoint {} is for a conversion with different options",
+                path.display()
+            );
+        }
+        Ok(checkpoint)
+    }
+}
+
+/// Writes checkpoints at a fixed interval
+pub(crate) struct Checkpointer {
+    path: PathBuf,
+    interval: Duration,
+    last_saved: Instant,
+}
+
+impl Checkpointer {
+    pub(crate) fn new(output_file: &Path, options: &CheckpointOptions) -> Self {
+        Checkpointer {
+            path: Checkpoint::path(output_file),
+            interval: Duration::from_secs(options.interval.unwrap_or(DEFAULT_INTERVAL)),
+            last_saved: Instant::now(),
+        }
+    }
+
+    pub(crate) fn is_due(&self) -> bool {
+        self.last_saved.elapsed() >= self.interval
+    }
+
+    /// Atomically replace the checkpoint file. All output must be flushed
+    /// (and synced) up to the offsets of `checkpoint` beforehand.
+    pub(crate) fn save(&mut self, checkpoint: &Checkpoint) -> Result<()> {
+        let file = AtomicFile::create(&self.path)?;
+        serde_json::to_writer_pretty(file.file(), checkpoint)
+            .output_context(|| format!("Error writing file {}", self.path.display()))?;
+        file.commit()?;
+        self.last_saved = Instant::now();
+        Ok(())
+    }
+
+    /// Remove the checkpoint of a completed conversion
+    pub(crate) fn remove(self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(err).output_context(|| format!("Error removing file {}", self.path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
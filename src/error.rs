@@ -0,0 +1,95 @@
+//! Classification of errors by what failed, for the exit code of the command
+//! line tool.
+//!
+//! Errors are classified by adding a context with [`ErrorContext`], which
+//! reads like (and replaces) `anyhow::Context::with_context`.
+
+use std::fmt;
+
+/// What an error was caused by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Reading the input (or another file given as an option) failed
+    Input,
+    /// The input couldn't be parsed
+    Parse,
+    /// Writing the output failed
+    Output,
+}
+
+impl ErrorKind {
+    /// Kind of `err`, i.e. of its outermost classified context
+    pub fn of(err: &anyhow::Error) -> Option<Self> {
+        err.downcast_ref::<Classified>().map(|c| c.kind)
+    }
+
+    /// Exit code of the command line tool. Unclassified errors exit with 1,
+    /// and invalid arguments with 2.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorKind::Input => 3,
+            ErrorKind::Parse => 4,
+            ErrorKind::Output => 5,
+        }
+    }
+}
+
+/// Context message of a classified error
+#[derive(Debug)]
+struct Classified {
+    kind: ErrorKind,
+    message: String,
+}
+
+impl fmt::Display for Classified {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+pub(crate) trait ErrorContext<T> {
+    /// Add a context message to the error, classifying it as `kind`
+    fn context_kind(self, kind: ErrorKind, message: impl FnOnce() -> String) -> anyhow::Result<T>;
+
+    fn input_context(self, message: impl FnOnce() -> String) -> anyhow::Result<T>
+    where
+        Self: Sized,
+    {
+        self.context_kind(ErrorKind::Input, message)
+    }
+
+    fn parse_context(self, message: impl FnOnce() -> String) -> anyhow::Result<T>
+    where
+        Self: Sized,
+    {
+        self.context_kind(ErrorKind::Parse, message)
+    }
+
+    fn output_context(self, message: impl FnOnce() -> String) -> anyhow::Result<T>
+    where
+        Self: Sized,
+    {
+        self.context_kind(ErrorKind::Output, message)
+    }
+}
+
+impl<T, E> ErrorContext<T> for Result<T, E>
+where
+    Result<T, E>: anyhow::Context<T, E>,
+{
+    fn context_kind(self, kind: ErrorKind, message: impl FnOnce() -> String) -> anyhow::Result<T> {
+        anyhow::Context::with_context(self, || Classified {
+            kind,
+            message: message(),
+        })
+    }
+}
+
+impl<T> ErrorContext<T> for Option<T> {
+    fn context_kind(self, kind: ErrorKind, message: impl FnOnce() -> String) -> anyhow::Result<T> {
+        anyhow::Context::with_context(self, || Classified {
+            kind,
+            message: message(),
+        })
+    }
+}
@@ -0,0 +1,66 @@
+//! OpenSearch/Elasticsearch `_bulk` requests to index converted names.
+//!
+//! Each name is an action line (indexing the row `id` into the names index)
+//! followed by its document: the name as in `convert --format json`, with the
+//! `id`, `pid`, `created`/`updated` timestamps and `version_id` of its
+//! `names_metadata` row. The action is versioned like InvenioRDM's own
+//! indexer does, so reloading a file doesn't overwrite newer documents.
+
+use anyhow::Result;
+
+use crate::{record_uuid, NameJson};
+
+/// Index names are loaded into by default, i.e. InvenioRDM's names index
+/// without a prefix
+pub const DEFAULT_ES_INDEX: &str = "names-name-v1.0.0";
+
+#[derive(serde::Serialize)]
+struct Action<'a> {
+    index: ActionMetadata<'a>,
+}
+
+#[derive(serde::Serialize)]
+struct ActionMetadata<'a> {
+    _index: &'a str,
+    _id: &'a str,
+    version: u8,
+    version_type: &'static str,
+}
+
+#[derive(serde::Serialize)]
+struct Document<'a> {
+    id: &'a str,
+    pid: &'a str,
+    created: &'a str,
+    updated: &'a str,
+    version_id: u8,
+    #[serde(flatten)]
+    name: &'a NameJson,
+}
+
+/// Encode a name as the action and document lines of a `_bulk` request
+pub(crate) fn encode(
+    orcid: &str,
+    name: &NameJson,
+    created_dt: &str,
+    index: &str,
+) -> Result<String> {
+    let id = record_uuid(orcid).to_string();
+    let action = Action {
+        index: ActionMetadata {
+            _index: index,
+            _id: &id,
+            version: 1,
+            version_type: "external_gte",
+        },
+    };
+    let document = Document {
+        id: &id,
+        pid: orcid,
+        created: created_dt,
+        updated: created_dt,
+        version_id: 1,
+        name,
+    };
+    Ok(serde_json::to_string(&action)? + "\n" + &serde_json::to_string(&document)? + "\n")
+}
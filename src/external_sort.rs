@@ -0,0 +1,229 @@
+//! Sorting and de-duplication of items that might not fit in memory.
+//!
+//! Items are buffered until the memory budget is reached, then sorted and
+//! spilled to disk as NDJSON "runs". Reading back k-way merges all runs, so
+//! memory usage stays flat regardless of the number of (distinct) items.
+
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use uuid::Uuid;
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Lines, Write},
+    mem,
+    path::{Path, PathBuf},
+};
+
+/// Maximum number of runs merged at once, to stay well below open file limits
+const MAX_MERGE_FAN_IN: usize = 64;
+
+/// Approximate in-memory size of an item, used to enforce the memory budget
+pub(crate) trait MemorySize {
+    fn memory_size(&self) -> usize;
+}
+
+pub(crate) struct ExternalSorter<T> {
+    buffer: Vec<T>,
+    buffer_size: usize,
+    memory_budget: usize,
+    dedup: bool,
+    tmp_dir: TempDir,
+    runs: Vec<PathBuf>,
+}
+
+impl<T: Serialize + DeserializeOwned + Ord + Clone + MemorySize> ExternalSorter<T> {
+    /// Create a sorter using at most `memory_budget` bytes for buffered items.
+    ///
+    /// Spilled runs are written to a new directory under `tmp_dir` (or the
+    /// system temporary directory), which is removed when the sorter is dropped.
+    pub(crate) fn new(memory_budget: usize, tmp_dir: Option<&Path>, dedup: bool) -> Result<Self> {
+        Ok(ExternalSorter {
+            buffer: Vec::new(),
+            buffer_size: 0,
+            memory_budget,
+            dedup,
+            tmp_dir: TempDir::new(tmp_dir)?,
+            runs: Vec::new(),
+        })
+    }
+
+    pub(crate) fn push(&mut self, item: T) -> Result<()> {
+        self.buffer_size += item.memory_size();
+        self.buffer.push(item);
+        if self.buffer_size >= self.memory_budget {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Sort the buffered items and write them to a new run file
+    fn spill(&mut self) -> Result<()> {
+        let mut items = mem::take(&mut self.buffer);
+        self.buffer_size = 0;
+        items.sort_unstable();
+        if self.dedup {
+            items.dedup();
+        }
+        let path = self.tmp_dir.run_path();
+        let mut writer = BufWriter::new(
+            File::create(&path)
+                .with_context(|| format!("Error creating file {}", path.display()))?,
+        );
+        for item in items {
+            serde_json::to_writer(&mut writer, &item)?;
+            writer.write_all(b"\n")?;
+        }
+        writer
+            .flush()
+            .with_context(|| format!("Error writing file {}", path.display()))?;
+        self.runs.push(path);
+        Ok(())
+    }
+
+    /// Consume the sorter, returning all items in sorted order
+    pub(crate) fn into_sorted_iter(mut self) -> Result<SortedIter<T>> {
+        if self.runs.is_empty() {
+            // Everything fit in memory, no need to touch the disk
+            let mut items = mem::take(&mut self.buffer);
+            items.sort_unstable();
+            if self.dedup {
+                items.dedup();
+            }
+            return Ok(SortedIter::Memory(items.into_iter()));
+        }
+        if !self.buffer.is_empty() {
+            self.spill()?;
+        }
+        // Merge runs in multiple passes if there are too many to open at once
+        while self.runs.len() > MAX_MERGE_FAN_IN {
+            let runs = mem::take(&mut self.runs);
+            for chunk in runs.chunks(MAX_MERGE_FAN_IN) {
+                let path = self.tmp_dir.run_path();
+                let mut writer = BufWriter::new(
+                    File::create(&path)
+                        .with_context(|| format!("Error creating file {}", path.display()))?,
+                );
+                for item in Merge::<T>::new(chunk, self.dedup)? {
+                    serde_json::to_writer(&mut writer, &item?)?;
+                    writer.write_all(b"\n")?;
+                }
+                writer
+                    .flush()
+                    .with_context(|| format!("Error writing file {}", path.display()))?;
+                for run in chunk {
+                    fs::remove_file(run)?;
+                }
+                self.runs.push(path);
+            }
+        }
+        Ok(SortedIter::Disk {
+            merge: Merge::new(&self.runs, self.dedup)?,
+            _tmp_dir: self.tmp_dir,
+        })
+    }
+}
+
+pub(crate) enum SortedIter<T> {
+    Memory(std::vec::IntoIter<T>),
+    Disk {
+        merge: Merge<T>,
+        // Keeps the run files around until the iterator is dropped
+        _tmp_dir: TempDir,
+    },
+}
+
+impl<T: DeserializeOwned + Ord + Clone> Iterator for SortedIter<T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            SortedIter::Memory(items) => items.next().map(Ok),
+            SortedIter::Disk { merge, .. } => merge.next(),
+        }
+    }
+}
+
+/// K-way merge of sorted run files
+pub(crate) struct Merge<T> {
+    readers: Vec<Lines<BufReader<File>>>,
+    heap: BinaryHeap<Reverse<(T, usize)>>,
+    last: Option<T>,
+    dedup: bool,
+}
+
+impl<T: DeserializeOwned + Ord> Merge<T> {
+    fn new(runs: &[PathBuf], dedup: bool) -> Result<Self> {
+        let mut merge = Merge {
+            readers: Vec::with_capacity(runs.len()),
+            heap: BinaryHeap::with_capacity(runs.len()),
+            last: None,
+            dedup,
+        };
+        for (idx, path) in runs.iter().enumerate() {
+            let file = File::open(path)
+                .with_context(|| format!("Error opening file {}", path.display()))?;
+            merge.readers.push(BufReader::new(file).lines());
+            merge.fill(idx)?;
+        }
+        Ok(merge)
+    }
+
+    /// Read the next item of run `idx` into the heap
+    fn fill(&mut self, idx: usize) -> Result<()> {
+        if let Some(line) = self.readers[idx].next() {
+            let item = serde_json::from_str(&line?)?;
+            self.heap.push(Reverse((item, idx)));
+        }
+        Ok(())
+    }
+}
+
+impl<T: DeserializeOwned + Ord + Clone> Iterator for Merge<T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Reverse((item, idx)) = self.heap.pop()?;
+            if let Err(err) = self.fill(idx) {
+                return Some(Err(err));
+            }
+            if self.dedup {
+                if self.last.as_ref() == Some(&item) {
+                    continue;
+                }
+                self.last = Some(item.clone());
+            }
+            return Some(Ok(item));
+        }
+    }
+}
+
+/// Temporary directory for run files, removed on drop
+pub(crate) struct TempDir {
+    path: PathBuf,
+    next_id: usize,
+}
+
+impl TempDir {
+    fn new(parent: Option<&Path>) -> Result<Self> {
+        let parent = parent.map_or_else(std::env::temp_dir, Path::to_path_buf);
+        let path = parent.join(format!("orcid-data-toolkit-{}", Uuid::new_v4()));
+        fs::create_dir_all(&path)
+            .with_context(|| format!("Error creating directory {}", path.display()))?;
+        Ok(TempDir { path, next_id: 0 })
+    }
+
+    fn run_path(&mut self) -> PathBuf {
+        self.next_id += 1;
+        self.path.join(format!("run-{:06}.ndjson", self.next_id))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
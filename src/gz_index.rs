@@ -0,0 +1,803 @@
+//! Random access into `.tar.gz` files, via a gzip checkpoint index.
+//!
+//! Like zlib's `zran.c`, the archive is decompressed once, recording a
+//! checkpoint about every `span` uncompressed bytes: the offsets of a deflate
+//! block boundary, plus the 32KiB of output preceding it (i.e. the window later
+//! blocks can refer back to). Decompression can then start at any checkpoint.
+//!
+//! The index also contains the uncompressed offset and size of each record in
+//! the tar archive, sorted by ORCiD iD, so a single record can be found with a
+//! binary search and decompressed from the closest preceding checkpoint.
+//!
+//! Each checkpoint also records where the first tar entry after it starts, so
+//! that the sections between checkpoints can be decompressed and read as tar
+//! archives independently, e.g. in parallel.
+//!
+//! Index file layout (all integers little-endian):
+//!
+//! - header: magic, archive size and modification time, span, checkpoints offset/count, members offset/count
+//! - windows: deflate-compressed windows of all checkpoints
+//! - checkpoints: compressed/uncompressed offset, bits, window offset/length,
+//!   uncompressed offset of the first tar entry
+//! - members: ORCiD iD, uncompressed offset and size of the record
+
+use std::{
+    cell::RefCell,
+    ffi::{c_int, c_uint},
+    fs::{File, Metadata},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    mem,
+    path::{Path, PathBuf},
+    ptr,
+    rc::Rc,
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{bail, Context, Result};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use libz_rs_sys as zlib;
+use serde::{Deserialize, Serialize};
+use tar::{Archive, Entry};
+
+use crate::atomic_file::AtomicFile;
+use crate::error::ErrorContext;
+use crate::external_sort::{ExternalSorter, MemorySize};
+use crate::orcids::is_orcid;
+
+const MAGIC: &[u8; 8] = b"ODTGZI03";
+const HEADER_SIZE: u64 = 8 + 7 * 8;
+const CHECKPOINT_SIZE: usize = 8 + 8 + 1 + 8 + 4 + 8;
+const ORCID_SIZE: usize = 19;
+const MEMBER_SIZE: usize = ORCID_SIZE + 8 + 8;
+
+/// Size of the deflate window, i.e. how far back compressed data can refer
+const WINDOW_SIZE: usize = 32 * 1024;
+const INPUT_BUFFER_SIZE: usize = 256 * 1024;
+
+/// `inflateInit2` window bits for raw deflate data
+const RAW_DEFLATE: c_int = -15;
+/// `inflateInit2` window bits for automatic detection of gzip/zlib headers
+const AUTO_HEADER: c_int = 15 + 32;
+
+/// Thin wrapper around a zlib inflate stream
+struct Inflater {
+    // Boxed, since the stream must not move once initialized
+    stream: Box<zlib::z_stream>,
+}
+
+impl Inflater {
+    fn new(window_bits: c_int) -> Result<Self> {
+        let mut stream = Box::<zlib::z_stream>::default();
+        // SAFETY: `stream` is a valid, default-initialized z_stream
+        let ret = unsafe {
+            zlib::inflateInit2_(
+                &mut *stream,
+                window_bits,
+                zlib::zlibVersion(),
+                mem::size_of::<zlib::z_stream>() as c_int,
+            )
+        };
+        if ret != zlib::Z_OK {
+            bail!("Error initializing decompression (zlib error {})", ret);
+        }
+        Ok(Inflater { stream })
+    }
+
+    fn reset(&mut self, window_bits: c_int) -> io::Result<()> {
+        // SAFETY: the stream was initialized in `new`
+        let ret = unsafe { zlib::inflateReset2(&mut *self.stream, window_bits) };
+        zlib_result(ret)
+    }
+
+    /// Feed the `bits` low bits of `value` into the stream, i.e. the end of a
+    /// byte that is shared with the previous deflate block
+    fn prime(&mut self, bits: u8, value: u8) -> io::Result<()> {
+        let value = c_int::from(value) >> (8 - bits);
+        // SAFETY: the stream was initialized in `new`
+        let ret = unsafe { zlib::inflatePrime(&mut *self.stream, c_int::from(bits), value) };
+        zlib_result(ret)
+    }
+
+    fn set_dictionary(&mut self, dictionary: &[u8]) -> io::Result<()> {
+        // SAFETY: the stream was initialized in `new`, and the dictionary is
+        // at most WINDOW_SIZE bytes long
+        let ret = unsafe {
+            zlib::inflateSetDictionary(
+                &mut *self.stream,
+                dictionary.as_ptr(),
+                dictionary.len() as c_uint,
+            )
+        };
+        zlib_result(ret)
+    }
+
+    /// Decompress `input` into `output`, returning the number of bytes
+    /// consumed and produced, and whether the end of the stream was reached
+    fn inflate(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        flush: c_int,
+    ) -> io::Result<(usize, usize, bool)> {
+        let output_len = output.len().min(c_uint::MAX as usize);
+        let stream = &mut *self.stream;
+        stream.next_in = input.as_ptr();
+        stream.avail_in = input.len() as c_uint;
+        stream.next_out = output.as_mut_ptr();
+        stream.avail_out = output_len as c_uint;
+        // SAFETY: the stream was initialized in `new`, and the input/output
+        // pointers are valid for `avail_in`/`avail_out` bytes
+        let ret = unsafe { zlib::inflate(stream, flush) };
+        let consumed = input.len() - stream.avail_in as usize;
+        let produced = output_len - stream.avail_out as usize;
+        // Don't keep pointers to the buffers around
+        stream.next_in = ptr::null();
+        stream.avail_in = 0;
+        stream.next_out = ptr::null_mut();
+        stream.avail_out = 0;
+        match ret {
+            zlib::Z_OK | zlib::Z_BUF_ERROR => Ok((consumed, produced, false)),
+            zlib::Z_STREAM_END => Ok((consumed, produced, true)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid compressed data (zlib error {})", ret),
+            )),
+        }
+    }
+
+    /// Whether the stream is at a deflate block boundary, which isn't the end
+    /// of the last block
+    fn at_block_boundary(&self) -> bool {
+        self.stream.data_type & 0xc0 == 0x80
+    }
+
+    /// Number of unused bits in the last consumed input byte
+    fn unused_bits(&self) -> u8 {
+        (self.stream.data_type & 7) as u8
+    }
+}
+
+impl Drop for Inflater {
+    fn drop(&mut self) {
+        // SAFETY: the stream was initialized in `new`
+        unsafe { zlib::inflateEnd(&mut *self.stream) };
+    }
+}
+
+fn zlib_result(ret: c_int) -> io::Result<()> {
+    match ret {
+        zlib::Z_OK => Ok(()),
+        _ => Err(io::Error::other(format!("zlib error {}", ret))),
+    }
+}
+
+/// The last `WINDOW_SIZE` bytes of decompressed output
+struct Window {
+    buf: Vec<u8>,
+    pos: usize,
+    full: bool,
+}
+
+impl Window {
+    fn new() -> Self {
+        Window {
+            buf: vec![0; WINDOW_SIZE],
+            pos: 0,
+            full: false,
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        let data = &data[data.len().saturating_sub(WINDOW_SIZE)..];
+        let first = data.len().min(WINDOW_SIZE - self.pos);
+        self.buf[self.pos..self.pos + first].copy_from_slice(&data[..first]);
+        self.buf[..data.len() - first].copy_from_slice(&data[first..]);
+        let end = self.pos + data.len();
+        self.full |= end >= WINDOW_SIZE;
+        self.pos = end % WINDOW_SIZE;
+    }
+
+    fn contents(&self) -> Vec<u8> {
+        match self.full {
+            true => [&self.buf[self.pos..], &self.buf[..self.pos]].concat(),
+            false => self.buf[..self.pos].to_vec(),
+        }
+    }
+}
+
+/// A point decompression can start from
+#[derive(Debug, Clone)]
+pub(crate) struct Checkpoint {
+    /// Offset in the compressed file, after the byte containing the block boundary
+    pub input: u64,
+    /// Offset in the decompressed data
+    pub output: u64,
+    /// Number of bits of the byte before `input` that belong to the next block
+    bits: u8,
+    window_offset: u64,
+    window_len: u32,
+    /// Offset in the decompressed data of the first tar entry (including any
+    /// extension headers) starting at or after `output`
+    first_entry: u64,
+}
+
+/// Size of a tar block, i.e. the alignment of tar entries
+const TAR_BLOCK_SIZE: u64 = 512;
+
+/// Reader decompressing a gzip file while recording checkpoints, with their
+/// (compressed) windows written to `windows`.
+///
+/// The checkpoints are shared, to fill in their first tar entry while the
+/// archive is read.
+struct IndexingReader<R, W> {
+    input: R,
+    input_buf: Vec<u8>,
+    input_pos: usize,
+    input_len: usize,
+    inflater: Inflater,
+    total_in: u64,
+    total_out: u64,
+    window: Window,
+    span: u64,
+    checkpoints: Rc<RefCell<Vec<Checkpoint>>>,
+    windows: W,
+    windows_offset: u64,
+}
+
+impl<R: Read, W: Write> IndexingReader<R, W> {
+    fn new(
+        input: R,
+        span: u64,
+        checkpoints: Rc<RefCell<Vec<Checkpoint>>>,
+        windows: W,
+        windows_offset: u64,
+    ) -> Result<Self> {
+        Ok(IndexingReader {
+            input,
+            input_buf: vec![0; INPUT_BUFFER_SIZE],
+            input_pos: 0,
+            input_len: 0,
+            inflater: Inflater::new(AUTO_HEADER)?,
+            total_in: 0,
+            total_out: 0,
+            window: Window::new(),
+            span,
+            checkpoints,
+            windows,
+            windows_offset,
+        })
+    }
+
+    /// Refill the input buffer if empty, returning false at the end of the input
+    fn fill_input(&mut self) -> io::Result<bool> {
+        if self.input_pos == self.input_len {
+            self.input_len = self.input.read(&mut self.input_buf)?;
+            self.input_pos = 0;
+        }
+        Ok(self.input_len > 0)
+    }
+
+    fn add_checkpoint(&mut self) -> io::Result<()> {
+        let mut encoder = DeflateEncoder::new(vec![], Compression::fast());
+        encoder.write_all(&self.window.contents())?;
+        let window = encoder.finish()?;
+        self.windows.write_all(&window)?;
+        self.checkpoints.borrow_mut().push(Checkpoint {
+            input: self.total_in,
+            output: self.total_out,
+            bits: self.inflater.unused_bits(),
+            window_offset: self.windows_offset,
+            window_len: window.len() as u32,
+            // Filled in once the entry is read
+            first_entry: u64::MAX,
+        });
+        self.windows_offset += window.len() as u64;
+        Ok(())
+    }
+}
+
+impl<R: Read, W: Write> Read for IndexingReader<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if !self.fill_input()? {
+                return Ok(0);
+            }
+            let input = &self.input_buf[self.input_pos..self.input_len];
+            // Stop at block boundaries, to be able to add checkpoints there
+            let (consumed, produced, stream_end) =
+                self.inflater.inflate(input, buf, zlib::Z_BLOCK)?;
+            self.input_pos += consumed;
+            self.total_in += consumed as u64;
+            self.total_out += produced as u64;
+            self.window.push(&buf[..produced]);
+
+            if stream_end {
+                // Concatenated gzip members start over with a new header
+                self.inflater.reset(AUTO_HEADER)?;
+            } else if self.inflater.at_block_boundary() {
+                let last_output = self.checkpoints.borrow().last().map(|c| c.output);
+                if last_output.is_none_or(|o| self.total_out - o >= self.span) {
+                    self.add_checkpoint()?;
+                }
+            }
+            if produced > 0 {
+                return Ok(produced);
+            }
+        }
+    }
+}
+
+/// Reader decompressing a gzip file, starting from a checkpoint
+pub(crate) struct CheckpointReader<R> {
+    input: R,
+    input_buf: Vec<u8>,
+    input_pos: usize,
+    input_len: usize,
+    inflater: Inflater,
+    /// Whether the current gzip member is read as raw deflate data, i.e.
+    /// without its header and trailer
+    raw: bool,
+}
+
+impl<R: Read + Seek> CheckpointReader<R> {
+    fn new(mut input: R, checkpoint: &Checkpoint, window: &[u8]) -> Result<Self> {
+        let mut inflater = Inflater::new(RAW_DEFLATE)?;
+        if checkpoint.bits > 0 {
+            input.seek(SeekFrom::Start(checkpoint.input - 1))?;
+            let mut byte = [0; 1];
+            input.read_exact(&mut byte)?;
+            inflater.prime(checkpoint.bits, byte[0])?;
+        } else {
+            input.seek(SeekFrom::Start(checkpoint.input))?;
+        }
+        if !window.is_empty() {
+            inflater.set_dictionary(window)?;
+        }
+        Ok(CheckpointReader {
+            input,
+            input_buf: vec![0; INPUT_BUFFER_SIZE],
+            input_pos: 0,
+            input_len: 0,
+            inflater,
+            raw: true,
+        })
+    }
+}
+
+impl<R: Read> CheckpointReader<R> {
+    fn fill_input(&mut self) -> io::Result<bool> {
+        if self.input_pos == self.input_len {
+            self.input_len = self.input.read(&mut self.input_buf)?;
+            self.input_pos = 0;
+        }
+        Ok(self.input_len > 0)
+    }
+
+    /// Skip the gzip trailer (CRC32 and size) of a member read as raw deflate data
+    fn skip_trailer(&mut self) -> io::Result<()> {
+        let mut remaining = 8;
+        while remaining > 0 {
+            if !self.fill_input()? {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let skipped = remaining.min(self.input_len - self.input_pos);
+            self.input_pos += skipped;
+            remaining -= skipped;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for CheckpointReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if !self.fill_input()? {
+                return Ok(0);
+            }
+            let input = &self.input_buf[self.input_pos..self.input_len];
+            let (consumed, produced, stream_end) =
+                self.inflater.inflate(input, buf, zlib::Z_NO_FLUSH)?;
+            self.input_pos += consumed;
+            if stream_end {
+                if self.raw {
+                    self.skip_trailer()?;
+                    self.raw = false;
+                }
+                // Concatenated gzip members start over with a new header
+                self.inflater.reset(AUTO_HEADER)?;
+            }
+            if produced > 0 {
+                return Ok(produced);
+            }
+        }
+    }
+}
+
+/// A record in the tar archive
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub(crate) struct Member {
+    pub orcid: String,
+    /// Offset of the record's data in the decompressed archive
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl MemorySize for Member {
+    fn memory_size(&self) -> usize {
+        mem::size_of::<Self>() + self.orcid.len()
+    }
+}
+
+/// Statistics of a built index
+pub(crate) struct IndexStats {
+    pub checkpoints: usize,
+    pub members: usize,
+}
+
+/// Build the index of the `.tar.gz` file at `archive_path`.
+///
+/// Members are sorted by ORCiD iD using at most `sort_memory` bytes (spilling to
+/// `tmp_dir` beyond that).
+pub(crate) fn build_index(
+    archive_path: &Path,
+    index_path: &Path,
+    span: u64,
+    sort_memory: usize,
+    tmp_dir: Option<&Path>,
+) -> Result<IndexStats> {
+    let archive_file = File::open(archive_path)
+        .input_context(|| format!("Error opening file {}", archive_path.display()))?;
+    let metadata = archive_file.metadata()?;
+    let archive_len = metadata.len();
+    let archive_mtime = modified_nanos(&metadata);
+    let index_file = AtomicFile::create(index_path)?;
+    let mut index = BufWriter::new(index_file.file().try_clone()?);
+    // Written once all offsets are known
+    index.write_all(&[0; HEADER_SIZE as usize])?;
+
+    let checkpoints = Rc::new(RefCell::new(vec![]));
+    let reader = IndexingReader::new(
+        archive_file,
+        span,
+        Rc::clone(&checkpoints),
+        index,
+        HEADER_SIZE,
+    )?;
+    let mut archive = Archive::new(BufReader::with_capacity(INPUT_BUFFER_SIZE, reader));
+    let mut sorter = ExternalSorter::new(sort_memory, tmp_dir, false)?;
+    // Checkpoints still missing their first entry
+    let mut pending = 0;
+    let mut entry_start = 0;
+    for entry in archive.entries().context("Error reading tar entries")? {
+        let entry = entry.context("Error reading tar entry")?;
+        // The reader is ahead of the entries, so all checkpoints before
+        // `entry_start` have been added already
+        for checkpoint in &mut checkpoints.borrow_mut()[pending..] {
+            if checkpoint.output > entry_start {
+                break;
+            }
+            checkpoint.first_entry = entry_start;
+            pending += 1;
+        }
+        // Directories and other entries that aren't records start sections too
+        let data_end = entry.raw_file_position() + entry.size();
+        entry_start = data_end.div_ceil(TAR_BLOCK_SIZE) * TAR_BLOCK_SIZE;
+
+        let path = entry.path()?;
+        if path.extension().and_then(|e| e.to_str()) != Some("xml") {
+            continue;
+        }
+        let Some(orcid) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        if is_orcid(orcid) {
+            sorter.push(Member {
+                orcid: orcid.to_string(),
+                offset: entry.raw_file_position(),
+                size: entry.size(),
+            })?;
+        }
+    }
+    let reader = archive.into_inner().into_inner();
+    let IndexingReader {
+        windows: mut index,
+        windows_offset: checkpoints_offset,
+        ..
+    } = reader;
+    let mut checkpoints = checkpoints.take();
+    // Sections after the last entry are empty
+    for checkpoint in &mut checkpoints[pending..] {
+        checkpoint.first_entry = entry_start.max(checkpoint.output);
+    }
+
+    for checkpoint in &checkpoints {
+        index.write_all(&checkpoint.input.to_le_bytes())?;
+        index.write_all(&checkpoint.output.to_le_bytes())?;
+        index.write_all(&[checkpoint.bits])?;
+        index.write_all(&checkpoint.window_offset.to_le_bytes())?;
+        index.write_all(&checkpoint.window_len.to_le_bytes())?;
+        index.write_all(&checkpoint.first_entry.to_le_bytes())?;
+    }
+    let members_offset = checkpoints_offset + (checkpoints.len() * CHECKPOINT_SIZE) as u64;
+    let mut member_count: u64 = 0;
+    for member in sorter.into_sorted_iter()? {
+        let member = member?;
+        index.write_all(member.orcid.as_bytes())?;
+        index.write_all(&member.offset.to_le_bytes())?;
+        index.write_all(&member.size.to_le_bytes())?;
+        member_count += 1;
+    }
+
+    index.seek(SeekFrom::Start(0))?;
+    index.write_all(MAGIC)?;
+    for value in [
+        archive_len,
+        archive_mtime,
+        span,
+        checkpoints_offset,
+        checkpoints.len() as u64,
+        members_offset,
+        member_count,
+    ] {
+        index.write_all(&value.to_le_bytes())?;
+    }
+    index
+        .flush()
+        .output_context(|| format!("Error writing file {}", index_path.display()))?;
+    index_file.commit()?;
+
+    Ok(IndexStats {
+        checkpoints: checkpoints.len(),
+        members: member_count as usize,
+    })
+}
+
+/// Modification time of a file in nanoseconds since the epoch, or 0 if unknown
+fn modified_nanos(metadata: &Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_nanos() as u64)
+}
+
+/// A part of the archive that can be read independently, i.e. the entries
+/// starting between two checkpoints
+#[derive(Debug, Clone)]
+pub(crate) struct Section {
+    checkpoint: usize,
+    /// Offset of the first entry in the decompressed data
+    start: u64,
+    /// Offset of the first entry of the next section
+    end: u64,
+}
+
+impl Section {
+    pub(crate) fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub(crate) fn end(&self) -> u64 {
+        self.end
+    }
+}
+
+/// An opened index file
+pub(crate) struct GzIndex {
+    path: PathBuf,
+    file: File,
+    checkpoints: Vec<Checkpoint>,
+    members_offset: u64,
+    member_count: u64,
+}
+
+impl GzIndex {
+    /// Open the index of the archive at `archive_path`
+    pub(crate) fn open(index_path: &Path, archive_path: &Path) -> Result<Self> {
+        let mut file = File::open(index_path)
+            .input_context(|| format!("Error opening index file {}", index_path.display()))?;
+        let index_len = file
+            .metadata()
+            .input_context(|| format!("Error reading index file {}", index_path.display()))?
+            .len();
+        let invalid = || format!("Invalid index file {}", index_path.display());
+        let mut header = [0; HEADER_SIZE as usize];
+        file.read_exact(&mut header)
+            .ok()
+            .filter(|_| header.starts_with(MAGIC))
+            .input_context(invalid)?;
+        let values: Vec<u64> = header[MAGIC.len()..]
+            .chunks_exact(8)
+            .map(|c| u64::from_le_bytes(c.try_into().expect("8 bytes")))
+            .collect();
+        let [archive_len, archive_mtime, _span, checkpoints_offset, checkpoint_count, members_offset, member_count] =
+            values[..]
+        else {
+            unreachable!("header has 7 values");
+        };
+
+        let metadata = std::fs::metadata(archive_path)
+            .input_context(|| format!("Error opening file {}", archive_path.display()))?;
+        if metadata.len() != archive_len || modified_nanos(&metadata) != archive_mtime {
+            bail!(
+                "Index file {} doesn't match {}, rebuild it with the `index` command",
+                index_path.display(),
+                archive_path.display()
+            );
+        }
+
+        // Both tables must lie within the index file
+        let table_end = |offset: u64, count: u64, size: usize| {
+            count
+                .checked_mul(size as u64)
+                .and_then(|len| offset.checked_add(len))
+                .filter(|&end| offset >= HEADER_SIZE && end <= index_len)
+        };
+        table_end(checkpoints_offset, checkpoint_count, CHECKPOINT_SIZE)
+            .and(table_end(members_offset, member_count, MEMBER_SIZE))
+            .input_context(invalid)?;
+
+        file.seek(SeekFrom::Start(checkpoints_offset))?;
+        let mut table = vec![0; checkpoint_count as usize * CHECKPOINT_SIZE];
+        file.read_exact(&mut table).input_context(invalid)?;
+        let checkpoints: Vec<Checkpoint> = table
+            .chunks_exact(CHECKPOINT_SIZE)
+            .map(|c| Checkpoint {
+                input: u64::from_le_bytes(c[0..8].try_into().expect("8 bytes")),
+                output: u64::from_le_bytes(c[8..16].try_into().expect("8 bytes")),
+                bits: c[16],
+                window_offset: u64::from_le_bytes(c[17..25].try_into().expect("8 bytes")),
+                window_len: u32::from_le_bytes(c[25..29].try_into().expect("4 bytes")),
+                first_entry: u64::from_le_bytes(c[29..37].try_into().expect("8 bytes")),
+            })
+            .collect();
+        for checkpoint in &checkpoints {
+            Some(checkpoint)
+                .filter(|c| c.input <= archive_len && c.bits < 8)
+                .and_then(|c| c.window_offset.checked_add(c.window_len.into()))
+                .filter(|&end| end <= index_len)
+                .input_context(invalid)?;
+        }
+
+        Ok(GzIndex {
+            path: index_path.to_path_buf(),
+            file,
+            checkpoints,
+            members_offset,
+            member_count,
+        })
+    }
+
+    fn read_member(&mut self, idx: u64) -> Result<Member> {
+        let mut buf = [0; MEMBER_SIZE];
+        self.file.seek(SeekFrom::Start(
+            self.members_offset + idx * MEMBER_SIZE as u64,
+        ))?;
+        self.file.read_exact(&mut buf)?;
+        Ok(Member {
+            orcid: String::from_utf8_lossy(&buf[..ORCID_SIZE]).into_owned(),
+            offset: u64::from_le_bytes(buf[ORCID_SIZE..ORCID_SIZE + 8].try_into()?),
+            size: u64::from_le_bytes(buf[ORCID_SIZE + 8..].try_into()?),
+        })
+    }
+
+    /// Binary search the member table for an ORCiD iD
+    pub(crate) fn find_member(&mut self, orcid: &str) -> Result<Option<Member>> {
+        let (mut low, mut high) = (0, self.member_count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let member = self.read_member(mid)?;
+            match member.orcid.as_str().cmp(orcid) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Ok(Some(member)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Read the window of a checkpoint. Opens the index file again, so that
+    /// windows can be read concurrently.
+    fn read_window(&self, checkpoint: &Checkpoint) -> Result<Vec<u8>> {
+        let mut file = File::open(&self.path)
+            .input_context(|| format!("Error opening index file {}", self.path.display()))?;
+        file.seek(SeekFrom::Start(checkpoint.window_offset))?;
+        let mut window = Vec::with_capacity(WINDOW_SIZE);
+        DeflateDecoder::new(file.take(u64::from(checkpoint.window_len)))
+            .read_to_end(&mut window)?;
+        Ok(window)
+    }
+
+    /// Open a reader of the decompressed archive, starting at `offset`
+    pub(crate) fn reader_at(
+        &self,
+        archive_path: &Path,
+        offset: u64,
+    ) -> Result<CheckpointReader<File>> {
+        let idx = self.checkpoints.partition_point(|c| c.output <= offset);
+        let Some(checkpoint) = idx.checked_sub(1).map(|idx| &self.checkpoints[idx]) else {
+            bail!("No checkpoint before offset {}", offset);
+        };
+        let window = self.read_window(checkpoint)?;
+
+        let archive = File::open(archive_path)
+            .input_context(|| format!("Error opening file {}", archive_path.display()))?;
+        let mut reader = CheckpointReader::new(archive, checkpoint, &window)?;
+        let skip = offset - checkpoint.output;
+        let skipped = io::copy(&mut (&mut reader).take(skip), &mut io::sink())?;
+        if skipped != skip {
+            bail!("Unexpected end of {}", archive_path.display());
+        }
+        Ok(reader)
+    }
+
+    /// Read the data of a member of the archive
+    pub(crate) fn read_member_data(&self, archive_path: &Path, member: &Member) -> Result<Vec<u8>> {
+        let reader = self.reader_at(archive_path, member.offset)?;
+        let mut data = Vec::with_capacity(member.size as usize);
+        reader.take(member.size).read_to_end(&mut data)?;
+        if data.len() as u64 != member.size {
+            bail!("Unexpected end of {}", archive_path.display());
+        }
+        Ok(data)
+    }
+}
+
+impl GzIndex {
+    /// The non-empty sections of the archive, in order
+    pub(crate) fn sections(&self) -> Vec<Section> {
+        let mut sections: Vec<Section> = vec![];
+        for (idx, checkpoint) in self.checkpoints.iter().enumerate() {
+            let start = checkpoint.first_entry;
+            match sections.last_mut() {
+                // Huge entries can span several checkpoints
+                Some(last) if last.start == start => continue,
+                Some(last) => last.end = start,
+                None => {}
+            }
+            sections.push(Section {
+                checkpoint: idx,
+                start,
+                end: u64::MAX,
+            });
+        }
+        sections
+    }
+
+    /// Call `f` with each entry of a section of the archive
+    pub(crate) fn for_each_entry<F>(
+        &self,
+        archive_path: &Path,
+        section: &Section,
+        mut f: F,
+    ) -> Result<()>
+    where
+        F: FnMut(Entry<'_, CheckpointReader<File>>) -> Result<()>,
+    {
+        let checkpoint = &self.checkpoints[section.checkpoint];
+        let window = self.read_window(checkpoint)?;
+        let archive_file = File::open(archive_path)
+            .input_context(|| format!("Error opening file {}", archive_path.display()))?;
+        let mut reader = CheckpointReader::new(archive_file, checkpoint, &window)?;
+        let skip = section.start - checkpoint.output;
+        if io::copy(&mut (&mut reader).take(skip), &mut io::sink())? != skip {
+            bail!("Unexpected end of {}", archive_path.display());
+        }
+
+        let mut archive = Archive::new(reader);
+        for entry in archive.entries().context("Error reading tar entries")? {
+            let entry = entry.context("Error reading tar entry")?;
+            if section.start + entry.raw_header_position() >= section.end {
+                break;
+            }
+            f(entry)?;
+        }
+        Ok(())
+    }
+}
@@ -1,205 +1,450 @@
+//! Converting the ORCiD Public Data Summaries to InvenioRDM names.
+//!
+//! Besides the `orcid-data-toolkit` command line tool, the crate can be used
+//! as a library: [`RecordReader`] reads the (typed) [`record::Record`]s of a
+//! `.tar.gz` file, a directory or an XML file, and [`NameConverter`] converts
+//! them to InvenioRDM names, with the same options as the `convert` command.
+//!
+//! ```
+//! use orcid_data_toolkit::{ConvertOptions, NameConverter, RecordReader};
+//!
+//! let converter = NameConverter::new(&ConvertOptions::default())?;
+//! for record in RecordReader::open("tests/data")? {
+//!     let Ok(record) = record else { continue }; // e.g. invalid XML
+//!     if let Some(name) = converter.convert(&record)? {
+//!         println!("{}", serde_json::to_string(&name)?);
+//!     }
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
 use anyhow::{bail, Context, Result};
-use chrono::Utc;
+use chrono::{DateTime, FixedOffset, Utc};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
 use std::{
     collections::{HashMap, HashSet},
     ffi::OsStr,
     fs::{self, File},
-    io::{stdout, BufWriter, Read, Write},
-    path::PathBuf,
-    sync::Mutex,
+    io::{stdout, BufWriter, Read, Seek, Write},
+    ops::Range,
+    path::{Path, PathBuf},
     thread,
 };
 
-use crossbeam_channel::{bounded, Sender};
+use crossbeam_channel::{bounded, Receiver, Sender};
 use flate2::read::GzDecoder;
 use rayon::prelude::*;
 use tar::Archive;
 
-use regex::Regex;
 use unicode_normalization::UnicodeNormalization;
 
-use quick_xml::de::Deserializer;
 use serde::Deserialize;
 
-use clap::ValueEnum;
+use clap::{Args, ValueEnum};
 
-#[derive(Debug, PartialEq, Default, Deserialize)]
-struct Identifier {
-    #[serde(rename = "uri")]
-    uri: String,
-    #[serde(rename = "path")]
-    path: String,
-}
+mod affiliations;
+use affiliations::Affiliated;
+pub use affiliations::AffiliationFilter;
+mod atomic_file;
+use atomic_file::AtomicFile;
+mod checkpoint;
+pub use checkpoint::CheckpointOptions;
+use checkpoint::{Checkpoint, Checkpointer, Member};
+mod error;
+use error::ErrorContext;
+pub use error::ErrorKind;
+mod es_bulk;
+pub use es_bulk::DEFAULT_ES_INDEX;
+mod external_sort;
+use external_sort::{ExternalSorter, MemorySize};
+mod gz_index;
+use gz_index::GzIndex;
+mod names;
+use names::{ComposedName, NameFilter};
+pub use names::{
+    Mononym, NameCase, NameExclusions, NamePolicy, DEFAULT_NAME_FILTER_REGEX, DEFAULT_NAME_TEMPLATE,
+};
+mod orcids;
+use orcids::OrcidFilter;
+pub use orcids::OrcidSelection;
+#[cfg(feature = "python")]
+mod python;
+mod rdf;
+mod reader;
+pub use reader::RecordReader;
+pub mod record;
+use record::{parse_xml, Organization, Record, RecordStatus};
+mod scripts;
+pub use scripts::ScriptOptions;
+mod shards;
+pub use shards::ShardOptions;
+use shards::Shards;
+mod validate;
+use validate::ReviewEntry;
+pub use validate::{NameCheck, NameCheckMode, NameChecks};
+mod vocab;
 
-#[derive(Debug, PartialEq, Default, Deserialize)]
-struct PersonName {
-    #[serde(rename = "given-names")]
-    given_names: Option<String>,
-    #[serde(rename = "family-name")]
-    family_name: Option<String>,
+/// An identifier of a name, e.g. its ORCiD iD
+#[derive(Debug, Clone, serde::Serialize)]
+#[non_exhaustive]
+pub struct NameIdentifier {
+    pub scheme: String,
+    pub identifier: String,
 }
 
-#[derive(Debug, PartialEq, Default, Deserialize)]
-struct Person {
-    name: PersonName,
+/// A current affiliation of a name, with its ROR ID if known
+#[derive(Debug, Clone, serde::Serialize)]
+#[non_exhaustive]
+pub struct NameAffiliation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
 }
 
-#[derive(Debug, PartialEq, Default, Deserialize)]
-struct OrgIdentifier {
-    #[serde(alias = "disambiguated-organization-identifier")]
-    identifier: String,
-    #[serde(alias = "disambiguation-source")]
-    source: String,
+/// An InvenioRDM name, serialized as in `convert --format json`
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "$schema", rename = "local://names/name-v1.0.0.json")]
+#[non_exhaustive]
+pub struct NameJson {
+    pub given_name: String,
+    pub family_name: String,
+    pub name: String,
+    pub identifiers: Vec<NameIdentifier>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub affiliations: Option<Vec<NameAffiliation>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alternative_names: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_latin: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scripts: Option<Vec<&'static str>>,
 }
 
-#[derive(Debug, PartialEq, Default, Deserialize)]
-struct Organization {
-    name: String,
-    #[serde(alias = "disambiguated-organization")]
-    identifier: Option<OrgIdentifier>,
+#[derive(serde::Serialize)]
+struct Row {
+    created: String,
+    updated: String,
+    id: String,
+    json: String,
+    version_id: u8,
+    pid: String,
 }
 
-#[derive(Debug, PartialEq, Default, Deserialize)]
-struct Employment {
-    #[serde(alias = "end-date")]
-    end: Option<()>,
-    organization: Organization,
+/// A deprecated record that was merged into another one
+#[derive(Debug, serde::Serialize)]
+struct Redirect {
+    orcid: String,
+    primary_orcid: String,
 }
 
-#[derive(Debug, PartialEq, Default, Deserialize)]
-struct AffiliationGroup {
-    #[serde(alias = "employment-summary")]
-    employment: Employment,
+/// A converted record that passed the name filter
+#[derive(Default)]
+struct Converted {
+    /// The converted name, unless rejected by the name checks or inactive
+    json: Option<NameJson>,
+    /// Present if the name was flagged by the name checks
+    review: Option<ReviewEntry>,
+    /// Present if the record is deprecated in favor of a known primary record
+    redirect: Option<Redirect>,
 }
 
-#[derive(Debug, PartialEq, Default, Deserialize)]
-struct Employments {
-    #[serde(alias = "affiliation-group")]
-    employment: Option<Vec<AffiliationGroup>>,
+/// Error for a record that the converter skips by design, i.e. one that is
+/// filtered out or has no name, as opposed to one that failed to convert
+#[derive(Debug)]
+struct Skipped {
+    reason: String,
+    /// Whether the record was filtered out by the options, rather than unusable
+    filtered: bool,
 }
 
-#[derive(Debug, PartialEq, Default, Deserialize)]
-struct Activities {
-    employments: Employments,
-}
+impl Skipped {
+    fn filtered(reason: String) -> Self {
+        Skipped {
+            reason,
+            filtered: true,
+        }
+    }
 
-#[derive(Debug, PartialEq, Default, Deserialize)]
-struct Record {
-    #[serde(alias = "orcid-identifier")]
-    identifier: Identifier,
-    person: Person,
-    #[serde(alias = "activities-summary")]
-    activities: Activities,
+    fn unusable(reason: String) -> Self {
+        Skipped {
+            reason,
+            filtered: false,
+        }
+    }
 }
 
-#[derive(Debug, serde::Serialize)]
-struct NameIdentifier {
-    scheme: String,
-    identifier: String,
+impl std::fmt::Display for Skipped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.reason)
+    }
 }
 
-#[derive(Debug, serde::Serialize)]
-struct NameAffiliation {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    id: Option<String>,
-    name: String,
-}
+impl std::error::Error for Skipped {}
 
-#[derive(Debug, serde::Serialize)]
-#[serde(tag = "$schema", rename = "local://names/name-v1.0.0.json")]
-struct NameJson {
-    given_name: String,
-    family_name: String,
-    name: String,
-    identifiers: Vec<NameIdentifier>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    affiliations: Option<Vec<NameAffiliation>>,
+/// Converts records to InvenioRDM names, as the `convert` command does.
+///
+/// ```
+/// use orcid_data_toolkit::{ConvertOptions, NameConverter, RecordReader};
+///
+/// let converter = NameConverter::new(&ConvertOptions::default())?.with_name_filter("^Ioannidis, ")?;
+/// for record in RecordReader::open("tests/data/alex.xml")? {
+///     let name = converter.convert(&record?)?.expect("active record");
+///     assert_eq!(name.family_name, "Ioannidis");
+///     assert_eq!(name.identifiers[0].identifier, "0000-0002-5082-6404");
+/// }
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct NameConverter {
+    options: ConvertOptions,
+    org_map: OrgMap,
+    name_filter: NameFilter,
 }
 
-#[derive(serde::Serialize)]
-struct Row {
-    created: String,
-    updated: String,
-    id: String,
-    json: String,
-    version_id: u8,
-    pid: String,
-}
+impl NameConverter {
+    /// Create a converter with the given options, without a name filter or
+    /// organization mappings
+    pub fn new(options: &ConvertOptions) -> Result<Self> {
+        Self::build(options, &None, &None)
+    }
 
-fn record_to_row(
-    record: &Record,
-    org_map: &OrgMap,
-    created_dt: &str,
-    name_filter: &Option<Regex>,
-) -> Result<Row> {
-    let name_json = record_to_json(record, org_map)?;
-    if let Some(ref re) = name_filter {
-        if !re.is_match(&name_json.name) {
-            bail!(
+    fn build(
+        options: &ConvertOptions,
+        filter_name: &Option<String>,
+        orgs_mappings_file: &Option<PathBuf>,
+    ) -> Result<Self> {
+        Ok(NameConverter {
+            options: options.clone(),
+            org_map: read_org_ids(orgs_mappings_file)?,
+            name_filter: NameFilter::new(filter_name, &options.exclude_names)?,
+        })
+    }
+
+    /// Fingerprint of everything that determines the converted records but the
+    /// creation timestamp (which resumed conversions keep from their
+    /// checkpoint), to only resume conversions with the same options
+    fn fingerprint(&self) -> String {
+        let mut options = self.options.clone();
+        options.checkpoints = CheckpointOptions::default();
+        options.timestamp = None;
+        let mut org_map: Vec<_> = self.org_map.iter().collect();
+        org_map.sort();
+        let fingerprint = format!("{:?}\n{:?}\n{:?}", options, self.name_filter, org_map);
+        format!("{:x}", Sha256::digest(fingerprint.as_bytes()))
+    }
+
+    /// Only convert records whose name matches the regular expression `pattern`
+    pub fn with_name_filter(mut self, pattern: &str) -> Result<Self> {
+        self.name_filter =
+            NameFilter::new(&Some(pattern.to_string()), &self.options.exclude_names)?;
+        Ok(self)
+    }
+
+    /// Resolve the ROR IDs of affiliations with the organization mappings
+    /// passed to `convert --orgs-mapping`, i.e. a CSV file of scheme,
+    /// identifier and ROR ID (e.g. curated from `extract --format org-stats-csv`)
+    pub fn with_org_mappings(mut self, path: &Path) -> Result<Self> {
+        self.org_map = read_org_ids(&Some(path.to_path_buf()))?;
+        Ok(self)
+    }
+
+    /// Convert a record. Returns `None` for records that are skipped by
+    /// design: inactive (i.e. deprecated, locked or deactivated) records,
+    /// records without a name, and names that are filtered out (by name,
+    /// affiliation or script) or rejected by the name checks.
+    ///
+    /// ```
+    /// use orcid_data_toolkit::{ConvertOptions, NameConverter, RecordReader};
+    ///
+    /// let converter = NameConverter::new(&ConvertOptions::default())?.with_name_filter("^M")?;
+    /// let mut names = vec![];
+    /// for record in RecordReader::open("tests/data/summaries.tar.gz")? {
+    ///     let Ok(record) = record else { continue }; // e.g. invalid XML
+    ///     if let Some(name) = converter.convert(&record)? {
+    ///         names.push(name.name);
+    ///     }
+    /// }
+    /// assert_eq!(names, ["Manghi, Paolo"]);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn convert(&self, record: &Record) -> Result<Option<NameJson>> {
+        match self.convert_record(record) {
+            Ok(converted) => Ok(converted.json),
+            Err(err) if err.is::<Skipped>() => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Convert a record, applying the name filter and name checks.
+    ///
+    /// Inactive (i.e. deprecated, locked or deactivated) records are skipped.
+    fn convert_record(&self, record: &Record) -> Result<Converted> {
+        match record.status() {
+            RecordStatus::Active => {}
+            status => {
+                let redirect = match status {
+                    RecordStatus::Deprecated {
+                        primary: Some(primary_orcid),
+                    } => Some(Redirect {
+                        orcid: record.identifier.path.to_string(),
+                        primary_orcid,
+                    }),
+                    _ => None,
+                };
+                return Ok(Converted {
+                    json: None,
+                    review: None,
+                    redirect,
+                });
+            }
+        }
+
+        let name_json = record_to_json(record, &self.org_map, &self.options)?;
+        if !self.name_filter.is_match(&name_json.name) {
+            bail!(Skipped::filtered(format!(
                 "Name {:?} filtered out from {:?}",
-                name_json.name,
-                record.identifier.path,
-            )
+                name_json.name, record.identifier.path,
+            )))
+        }
+
+        let checks = &self.options.name_checks;
+        let failed = checks.check(&name_json.given_name, &name_json.family_name);
+        if failed.is_empty() {
+            return Ok(Converted {
+                json: Some(name_json),
+                review: None,
+                redirect: None,
+            });
         }
+        let rejected = checks.mode == NameCheckMode::Reject;
+        let review = ReviewEntry {
+            orcid: record.identifier.path.to_string(),
+            checks: failed
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(";"),
+            rejected,
+            given_name: name_json.given_name.clone(),
+            family_name: name_json.family_name.clone(),
+            name: name_json.name.clone(),
+        };
+        Ok(Converted {
+            json: (!rejected).then_some(name_json),
+            review: Some(review),
+            redirect: None,
+        })
     }
+}
+
+/// Name-based UUID of a record, so that its `id` is the same in every export.
+///
+/// Like a version 5 UUID, but using SHA-256 (i.e. a version 8 UUID, as in the
+/// example of RFC 9562) of the ORCiD URI in the URL namespace.
+fn record_uuid(orcid: &str) -> Uuid {
+    let mut hasher = Sha256::new();
+    hasher.update(Uuid::NAMESPACE_URL.as_bytes());
+    hasher.update(format!("https://orcid.org/{}", orcid).as_bytes());
+    let digest = hasher.finalize();
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    Uuid::new_v8(bytes)
+}
+
+fn record_to_row(record: &Record, name_json: &NameJson, created_dt: &str) -> Result<Row> {
     Ok(Row {
         created: String::from(created_dt),
         updated: String::from(created_dt),
-        id: Uuid::new_v4().to_string(),
-        pid: String::from(record.identifier.path.as_str()),
+        id: record_uuid(&record.identifier.path).to_string(),
+        pid: record.identifier.path.to_string(),
         version_id: 1,
-        json: serde_json::to_string(&name_json)?,
+        json: serde_json::to_string(name_json)?,
     })
 }
 
-fn record_to_json(record: &Record, org_map: &OrgMap) -> Result<NameJson> {
-    let mut affiliations: Vec<NameAffiliation> = vec![];
-    let employments = record.activities.employments.employment.as_ref();
-    if let Some(_employments) = employments {
-        _employments
-            .iter()
-            .filter_map(|a| match a.employment.end {
-                // Past employment (i.e. end date is present)
-                Some(_) => None,
-                // Active employment (i.e. no end date)
-                None => {
-                    // Check for ROR ID
-                    let ror_id = match &a.employment.organization.identifier {
-                        Some(identifier) if identifier.source == "ROR" => identifier
-                            .identifier
-                            .as_str()
-                            .rsplit_once('/')
-                            .map(|(_, id)| id.to_string()),
-                        // Check for ROR ID in the org_map
-                        Some(identifer) => {
-                            let normalized_id = match identifer.source.as_str() {
-                                // Keep last part of FUNDREF, similar to ROR
-                                "FUNDREF" => identifer
-                                    .identifier
-                                    .rsplit_once('/')
-                                    .map(|(_, id)| id.to_string()),
-                                _ => Some(identifer.identifier.clone()),
-                            };
-                            normalized_id.and_then(|id| {
-                                org_map
-                                    .get(&ExtractedIdentifier {
-                                        scheme: identifer.source.clone(),
-                                        identifier: id,
-                                    })
-                                    .cloned()
-                            })
-                        }
-                        _ => None,
-                    };
-                    Some(NameAffiliation {
-                        name: a.employment.organization.name.nfc().to_string(),
-                        id: ror_id,
+/// Open the CSV file flagged records are written to, if one was requested
+fn open_review_file(checks: &NameChecks) -> Result<Option<CsvOutput>> {
+    open_csv_file(&checks.review_file)
+}
+
+fn open_csv_file(path: &Option<PathBuf>) -> Result<Option<CsvOutput>> {
+    path.as_deref().map(CsvOutput::create).transpose()
+}
+
+/// Reopen a CSV file to continue writing at `offset` (see `AtomicFile::reopen`)
+fn reopen_csv_file(path: &Option<PathBuf>, offset: Option<u64>) -> Result<Option<CsvOutput>> {
+    let (Some(path), Some(offset)) = (path, offset) else {
+        return open_csv_file(path);
+    };
+    CsvOutput::reopen(path, offset).map(Some)
+}
+
+/// Flush and sync a CSV file, returning its size
+fn sync_csv_file(output: Option<&mut CsvOutput>) -> Result<Option<u64>> {
+    output.map(CsvOutput::sync).transpose()
+}
+
+/// Resolve the ROR ID of an organization, either directly or via the org_map
+fn resolve_ror_id(organization: &Organization, org_map: &OrgMap) -> Option<String> {
+    match &organization.identifier {
+        Some(identifier) if identifier.source == "ROR" => identifier
+            .identifier
+            .rsplit_once('/')
+            .map(|(_, id)| id.to_string()),
+        // Check for ROR ID in the org_map
+        Some(identifer) => {
+            let normalized_id = match identifer.source.as_ref() {
+                // Keep last part of FUNDREF, similar to ROR
+                "FUNDREF" => identifer
+                    .identifier
+                    .rsplit_once('/')
+                    .map(|(_, id)| id.to_string()),
+                _ => Some(identifer.identifier.to_string()),
+            };
+            normalized_id.and_then(|id| {
+                org_map
+                    .get(&ExtractedIdentifier {
+                        scheme: identifer.source.to_string(),
+                        identifier: id,
                     })
-                }
+                    .cloned()
             })
-            .for_each(|n| affiliations.push(n));
+        }
+        _ => None,
+    }
+}
+
+fn record_to_json(record: &Record, org_map: &OrgMap, options: &ConvertOptions) -> Result<NameJson> {
+    let mut affiliations: Vec<NameAffiliation> = vec![];
+    let mut affiliated: Vec<Affiliated> = vec![];
+    let employments = record.activities.employments.employment.as_ref();
+    for a in employments.into_iter().flatten() {
+        let organization = &a.employment.organization;
+        let ror_id = resolve_ror_id(organization, org_map);
+        // Active employment (i.e. no end date)
+        let current = a.employment.end.is_none();
+        if current {
+            affiliations.push(NameAffiliation {
+                name: organization.name.nfc().to_string(),
+                id: ror_id.clone(),
+            });
+        }
+        affiliated.push(Affiliated {
+            ror_id,
+            country: organization
+                .address
+                .as_ref()
+                .and_then(|a| a.country.as_deref()),
+            current,
+        });
+    }
+    if !options.affiliations.is_match(&affiliated) {
+        bail!(Skipped::filtered(format!(
+            "Record {:?} filtered out by affiliation",
+            record.identifier.path
+        )));
     }
 
     // Deduplicate affiliations by their `id`
@@ -222,282 +467,1088 @@ fn record_to_json(record: &Record, org_map: &OrgMap) -> Result<NameJson> {
         }
     });
 
-    let (given_name, family_name, name) = match &record.person.name {
-        // If either value is present, use it
-        PersonName {
-            given_names: Some(name),
-            family_name: None,
-        }
-        | PersonName {
-            given_names: None,
-            family_name: Some(name),
-        } if !name.trim().is_empty() => (String::new(), name.clone(), name.clone()),
-
-        // If both values are present, combine them
-        PersonName {
-            given_names: Some(given_names),
-            family_name: Some(family_name),
-        } if !given_names.trim().is_empty() && !family_name.trim().is_empty() => (
-            given_names.clone(),
-            family_name.clone(),
-            format!("{}, {}", family_name, given_names),
-        ),
-        _ => bail!("Can't determine person name from {:?}", record.person.name,),
+    let policy = &options.name_policy;
+    let person_name = &record.person.name;
+    let Some(ComposedName {
+        given_name,
+        family_name,
+        name,
+    }) = policy.compose(
+        person_name.given_names.as_deref(),
+        person_name.family_name.as_deref(),
+    )
+    else {
+        bail!(Skipped::unusable(format!(
+            "Can't determine person name from {:?}",
+            person_name
+        )));
+    };
+
+    // The credit name is how the person's name appears on their publications
+    let credit_name = record.person.name.credit_name.as_deref().map(str::trim);
+    let credit_name = credit_name
+        .filter(|n| !n.is_empty())
+        .map(|n| policy.normalize(n));
+    let (name, mut alternative_names) = match credit_name {
+        Some(credit_name) if options.use_credit_name => (credit_name, vec![name]),
+        Some(credit_name) => (name, vec![credit_name]),
+        None => (name, vec![]),
     };
+    let other_names = record.person.other_names.as_ref();
+    alternative_names.extend(
+        other_names
+            .and_then(|o| o.names.as_ref())
+            .into_iter()
+            .flatten()
+            .map(|o| o.content.trim())
+            .filter(|n| !n.is_empty())
+            .map(|n| policy.normalize(n)),
+    );
+    let mut seen_names = HashSet::from([name.clone()]);
+    alternative_names.retain(|n| seen_names.insert(n.clone()));
+
+    let name_scripts = scripts::scripts([given_name.as_str(), &family_name, &name]);
+    if !options.scripts.is_allowed(&name_scripts) {
+        bail!(Skipped::filtered(format!(
+            "Name {:?} filtered out from {:?} by script",
+            name, record.identifier.path,
+        )));
+    }
+    let name_latin = options
+        .scripts
+        .transliterate
+        .then(|| scripts::transliterate(&name, &name_scripts))
+        .flatten();
 
     Ok(NameJson {
-        given_name: given_name.nfc().to_string(),
-        family_name: family_name.nfc().to_string(),
-        name: name.nfc().to_string(),
-        identifiers: vec![NameIdentifier {
-            scheme: "orcid".to_string(),
-            identifier: record.identifier.path.clone(),
-        }],
+        given_name,
+        family_name,
+        name,
+        identifiers: name_identifiers(record, options),
         affiliations: (!affiliations.is_empty()).then_some(affiliations),
+        alternative_names: (options.other_names && !alternative_names.is_empty())
+            .then_some(alternative_names),
+        name_latin,
+        scripts: options
+            .scripts
+            .annotate
+            .then(|| name_scripts.iter().map(|s| s.full_name()).collect()),
     })
 }
 
-fn iter_records<R: Read>(entries: tar::Entries<'_, R>) -> impl Iterator<Item = Record> + '_ {
-    entries
-        .filter_map(|entry_result| {
-            let entry = entry_result.ok()?;
-            let path = entry.path().ok()?;
-            if path.extension().and_then(OsStr::to_str) == Some("xml") {
-                Some(entry)
-            } else {
-                None
-            }
-        })
-        .filter_map(|mut entry| -> Option<Record> {
-            let mut xml_content = String::new();
-            entry.read_to_string(&mut xml_content).ok()?;
-            parse_xml(&xml_content)
-        })
+fn name_identifiers(record: &Record, options: &ConvertOptions) -> Vec<NameIdentifier> {
+    let mut identifiers = vec![NameIdentifier {
+        scheme: "orcid".to_string(),
+        identifier: record.identifier.path.to_string(),
+    }];
+    let external_identifiers = record.person.external_identifiers.as_ref();
+    let external_identifiers = external_identifiers
+        .and_then(|e| e.identifiers.as_ref())
+        .into_iter()
+        .flatten();
+    for id in external_identifiers {
+        // Only identifier types in the allowlist are included
+        let Some(scheme) = options
+            .person_ids
+            .iter()
+            .find(|s| s.id_type == id.id_type.trim())
+        else {
+            continue;
+        };
+        let identifier = id.value.trim();
+        let is_duplicate = identifiers
+            .iter()
+            .any(|i| i.scheme == scheme.scheme && i.identifier == identifier);
+        if !identifier.is_empty() && !is_duplicate {
+            identifiers.push(NameIdentifier {
+                scheme: scheme.scheme.clone(),
+                identifier: identifier.to_string(),
+            });
+        }
+    }
+    identifiers
 }
 
-/// Parse XML string into a Record, logging errors
-fn parse_xml(xml_content: &str) -> Option<Record> {
-    let rd = &mut Deserializer::from_str(xml_content);
-    match serde_path_to_error::deserialize(rd) {
-        Ok(record) => Some(record),
-        Err(err) => {
-            eprintln!("Error parsing XML: {}", err.path());
-            None
+/// Append an archive member to `xml_content`. Members that aren't valid UTF-8
+/// are logged and skipped (returning `false`), like records that fail to parse.
+fn read_member(member: &mut impl Read, path: &str, xml_content: &mut String) -> Result<bool> {
+    let start = xml_content.len();
+    match member.read_to_string(xml_content) {
+        Ok(_) => Ok(true),
+        Err(err) if err.kind() == std::io::ErrorKind::InvalidData => {
+            eprintln!("Error reading {}: {}", path, err);
+            xml_content.truncate(start);
+            Ok(false)
         }
+        Err(err) => Err(err).input_context(|| format!("Error reading {}", path)),
     }
 }
 
-/// Read tar entries sequentially and send XML strings to channel
-fn read_tar_entries_to_channel<R: Read>(entries: tar::Entries<'_, R>, tx: Sender<String>) {
+/// Call `f` with each active record of a `.tar.gz` file that is selected by
+/// `orcid_filter`, reusing the buffer entries are read into
+fn for_each_record<R: Read>(
+    input_file: &Path,
+    entries: tar::Entries<'_, R>,
+    orcid_filter: &OrcidFilter,
+    mut f: impl FnMut(&Record) -> Result<()>,
+) -> Result<()> {
+    let mut xml_content = String::new();
     for entry_result in entries {
-        let Ok(mut entry) = entry_result else {
+        let mut entry = entry_result
+            .input_context(|| format!("Error reading file {}", input_file.display()))?;
+        let path = entry
+            .path()
+            .input_context(|| format!("Error reading file {}", input_file.display()))?;
+        if path.extension().and_then(OsStr::to_str) != Some("xml")
+            || !orcid_filter.is_path_match(&path)
+        {
             continue;
-        };
-        let Ok(path) = entry.path() else { continue };
-        if path.extension().and_then(OsStr::to_str) != Some("xml") {
+        }
+        let path = path.display().to_string();
+        xml_content.clear();
+        if !read_member(&mut entry, &path, &mut xml_content)? {
             continue;
         }
-        let mut xml_content = String::new();
-        if entry.read_to_string(&mut xml_content).is_ok() {
-            // If receiver is dropped, stop reading
-            if tx.send(xml_content).is_err() {
-                break;
-            }
+        match parse_xml(&mut xml_content) {
+            Some(record) if record.status() == RecordStatus::Active => f(&record)?,
+            _ => {}
         }
     }
+    Ok(())
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum ConvertFormat {
+    /// Rows of the InvenioRDM `names_metadata` table, as CSV
     InvenioRDMNames,
+    /// Names as JSON Lines, like the `json` column of the table
     JSON,
+    /// Entries of an `invenio vocabularies import` names datastream, as YAML
+    InvenioVocabYAML,
+    /// Entries of an `invenio vocabularies import` names datastream, as JSON Lines
+    InvenioVocabJSONL,
+    /// OpenSearch/Elasticsearch `_bulk` requests to index the names
+    EsBulk,
+    /// schema.org persons, as N-Triples
+    #[value(name = "ntriples")]
+    NTriples,
+    /// schema.org persons, as JSON-LD documents (one per line)
+    #[value(name = "jsonld")]
+    JSONLD,
+}
+
+impl std::fmt::Display for ConvertFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = self.to_possible_value().expect("no skipped variants");
+        f.write_str(value.get_name())
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum ExtractFormat {
+    /// Unique employment organization identifiers
+    #[value(alias = "org-ids")]
     OrgIDs,
+    /// Unique person external identifiers (e.g. Scopus Author ID, ResearcherID)
+    #[value(name = "person-ids")]
+    PersonIDs,
+    /// Unique researcher URLs
+    #[value(name = "researcher-urls")]
+    ResearcherURLs,
+    /// Unique keywords
+    Keywords,
+    /// Unique domains of public emails
+    EmailDomains,
+    /// Unique countries of person addresses
+    Countries,
+    /// Per organization identifier counts, names and locations as NDJSON
+    OrgStats,
+    /// Same as `org-stats`, flattened to the most common name/location as CSV
+    #[value(name = "org-stats-csv")]
+    OrgStatsCSV,
 }
 
 type OrgMap = HashMap<ExtractedIdentifier, String>;
 
+/// Default InvenioRDM scheme names for well-known person external identifier types
+const PERSON_ID_SCHEMES: [(&str, &str); 5] = [
+    ("Scopus Author ID", "scopus"),
+    ("ResearcherID", "researcherid"),
+    ("Loop profile", "loop"),
+    ("ISNI", "isni"),
+    ("GND", "gnd"),
+];
+
+/// A person external identifier type mapped to an InvenioRDM names scheme
+#[derive(Debug, Clone, PartialEq)]
+pub struct PersonIdScheme {
+    pub id_type: String,
+    pub scheme: String,
+}
+
+impl std::str::FromStr for PersonIdScheme {
+    type Err = String;
+
+    /// Parse `TYPE[=SCHEME]`, defaulting the scheme for well-known types or to
+    /// the lowercased type with non-alphanumeric characters removed.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (id_type, scheme) = match s.split_once('=') {
+            Some((id_type, scheme)) => (id_type.trim(), scheme.trim().to_string()),
+            None => {
+                let id_type = s.trim();
+                let scheme = PERSON_ID_SCHEMES
+                    .iter()
+                    .find(|(t, _)| *t == id_type)
+                    .map(|(_, scheme)| scheme.to_string())
+                    .unwrap_or_else(|| {
+                        id_type
+                            .chars()
+                            .filter(|c| c.is_alphanumeric())
+                            .flat_map(char::to_lowercase)
+                            .collect()
+                    });
+                (id_type, scheme)
+            }
+        };
+        if id_type.is_empty() || scheme.is_empty() {
+            return Err(format!("Invalid person identifier scheme {:?}", s));
+        }
+        Ok(PersonIdScheme {
+            id_type: id_type.to_string(),
+            scheme,
+        })
+    }
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct ConvertOptions {
+    /// Person external identifier type to include in the name identifiers (e.g.
+    /// "Scopus Author ID"), optionally mapped to a scheme name ("ResearcherID=wos").
+    /// Can be repeated; types that are not listed are left out.
+    #[arg(long = "person-id", value_name = "TYPE[=SCHEME]")]
+    pub person_ids: Vec<PersonIdScheme>,
+
+    /// Use the credit name (i.e. published name) as the display name when present
+    #[arg(long = "use-credit-name")]
+    pub use_credit_name: bool,
+
+    /// Include other names (and the credit name) as `alternative_names`
+    #[arg(long = "other-names")]
+    pub other_names: bool,
+
+    #[command(flatten)]
+    pub name_policy: NamePolicy,
+
+    #[command(flatten)]
+    pub name_checks: NameChecks,
+
+    #[command(flatten)]
+    pub exclude_names: NameExclusions,
+
+    #[command(flatten)]
+    pub scripts: ScriptOptions,
+
+    #[command(flatten)]
+    pub orcids: OrcidSelection,
+
+    #[command(flatten)]
+    pub affiliations: AffiliationFilter,
+
+    /// Path to a CSV file to write the ORCiD iDs of deprecated records, and the
+    /// primary records they were merged into, to
+    #[arg(long = "redirects-file")]
+    pub redirects_file: Option<PathBuf>,
+
+    /// Creation/update timestamp of InvenioRDM names, in RFC 3339 format (e.g.
+    /// "2024-10-01T00:00:00Z"). Defaults to the current time.
+    #[arg(long = "timestamp")]
+    pub timestamp: Option<DateTime<FixedOffset>>,
+
+    /// Index the names of `--format es-bulk` are loaded into. Defaults to
+    /// "names-name-v1.0.0".
+    #[arg(long = "es-index", value_name = "INDEX")]
+    pub es_index: Option<String>,
+
+    #[command(flatten)]
+    pub sort: SortOptions,
+
+    #[command(flatten)]
+    pub shards: ShardOptions,
+
+    #[command(flatten)]
+    pub checkpoints: CheckpointOptions,
+}
+
+impl ConvertOptions {
+    /// Creation/update timestamp of the converted records
+    fn created_timestamp(&self) -> String {
+        match &self.timestamp {
+            Some(timestamp) => timestamp.to_rfc3339(),
+            None => Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Index names are loaded into with `--format es-bulk`
+    fn es_index(&self) -> &str {
+        self.es_index.as_deref().unwrap_or(DEFAULT_ES_INDEX)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    /// ORCiD iD
+    Orcid,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct SortOptions {
+    /// Sort the output by the given key, instead of keeping the order of the
+    /// records in the archive
+    #[arg(long = "sort-by", value_enum)]
+    pub sort_by: Option<SortKey>,
+
+    /// Memory budget (in MB) for sorting the output. Beyond it, sorted runs
+    /// are written to disk and merged at the end.
+    #[arg(long = "sort-memory", value_name = "MB", default_value_t = 256)]
+    pub sort_memory: usize,
+
+    /// Directory for temporary files (defaults to the system temporary directory)
+    #[arg(long = "tmp-dir")]
+    pub tmp_dir: Option<PathBuf>,
+}
+
+/// An encoded line of the output, with its sort key
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, Deserialize)]
+struct OutputLine {
+    orcid: String,
+    line: String,
+}
+
+impl MemorySize for OutputLine {
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.orcid.len() + self.line.len()
+    }
+}
+
+/// Encode a converted record as a line of the output `format`
+fn encode_line(
+    record: &Record,
+    name_json: &NameJson,
+    format: &ConvertFormat,
+    created_dt: &str,
+    es_index: &str,
+) -> Result<String> {
+    match format {
+        ConvertFormat::JSON => Ok(serde_json::to_string(name_json)? + "\n"),
+        ConvertFormat::InvenioRDMNames => {
+            let row = record_to_row(record, name_json, created_dt)?;
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(vec![]);
+            writer.serialize(row)?;
+            let line = writer.into_inner().context("Error writing CSV")?;
+            Ok(String::from_utf8(line)?)
+        }
+        ConvertFormat::InvenioVocabYAML => vocab::encode_yaml(&record.identifier.path, name_json),
+        ConvertFormat::InvenioVocabJSONL => vocab::encode_jsonl(&record.identifier.path, name_json),
+        ConvertFormat::EsBulk => {
+            es_bulk::encode(&record.identifier.path, name_json, created_dt, es_index)
+        }
+        ConvertFormat::NTriples => rdf::encode_ntriples(&record.identifier.path, name_json),
+        ConvertFormat::JSONLD => rdf::encode_jsonld(&record.identifier.path, name_json),
+    }
+}
+
 /// Batch size for processing - larger = less synchronization overhead
 const BATCH_SIZE: usize = 256;
 
+/// XML entries read from the archive, sent from the producer to the workers.
+/// Processed batches are sent back to the producer to reuse their buffers.
+#[derive(Default)]
+struct Batch {
+    /// Contents of the XML entries, one after the other
+    xml: String,
+    /// Range of each entry in `xml`
+    entries: Vec<Range<usize>>,
+    /// Last member read for this batch (including skipped ones)
+    last_member: Option<Member>,
+}
+
+impl Batch {
+    /// A processed batch to reuse, or otherwise a new one
+    fn reuse(recycled: &Receiver<Batch>) -> Self {
+        recycled.try_recv().unwrap_or_default()
+    }
+
+    /// Append an archive member (see `read_member`)
+    fn push(&mut self, member: &mut impl Read, path: &str) -> Result<()> {
+        let start = self.xml.len();
+        if read_member(member, path, &mut self.xml)? {
+            self.entries.push(start..self.xml.len());
+        }
+        Ok(())
+    }
+
+    fn xml_contents(&mut self) -> impl IndexedParallelIterator<Item = &mut str> {
+        // Split the buffer into the entries, so they can be parsed in place
+        let mut rest = self.xml.as_mut_str();
+        let mut offset = 0;
+        let contents: Vec<&mut str> = self
+            .entries
+            .iter()
+            .map(|range| {
+                let (_, tail) = std::mem::take(&mut rest).split_at_mut(range.start - offset);
+                let (content, tail) = tail.split_at_mut(range.len());
+                rest = tail;
+                offset = range.end;
+                content
+            })
+            .collect();
+        contents.into_par_iter()
+    }
+
+    /// Empty the batch for reuse, keeping its buffers
+    fn clear(&mut self) {
+        self.xml.clear();
+        self.entries.clear();
+        self.last_member = None;
+    }
+}
+
+/// Where `convert_tgz` writes the converted records to
+struct ConvertOutput {
+    /// Path of the output, for error messages
+    path: PathBuf,
+    stream: Box<dyn std::io::Write + Send>,
+    shards: Option<Shards>,
+    /// The output file (if any), kept to sync it before writing checkpoints
+    file: Option<AtomicFile>,
+    /// Number of bytes written to `stream`
+    offset: u64,
+}
+
+impl ConvertOutput {
+    fn write_line(&mut self, line: &OutputLine) -> Result<()> {
+        if let Some(shards) = self.shards.as_mut() {
+            return shards.write_line(&line.orcid, line.line.as_bytes());
+        }
+        self.stream
+            .write_all(line.line.as_bytes())
+            .output_context(|| format!("Error writing file {}", self.path.display()))?;
+        self.offset += line.line.len() as u64;
+        Ok(())
+    }
+
+    /// Flush and sync the output, returning its size
+    fn sync(&mut self) -> Result<u64> {
+        self.stream
+            .flush()
+            .output_context(|| format!("Error writing file {}", self.path.display()))?;
+        if let Some(file) = &self.file {
+            file.file()
+                .sync_data()
+                .output_context(|| format!("Error writing file {}", self.path.display()))?;
+        }
+        Ok(self.offset)
+    }
+
+    fn finish(mut self) -> Result<()> {
+        self.stream
+            .flush()
+            .output_context(|| format!("Error writing file {}", self.path.display()))?;
+        if let Some(shards) = self.shards {
+            shards.finish()?;
+        }
+        if let Some(file) = self.file {
+            file.commit()?;
+        }
+        Ok(())
+    }
+}
+
+/// The output of a command, i.e. stdout for "-", or otherwise a file that
+/// replaces the output file once committed
+struct Output {
+    path: PathBuf,
+    stream: Box<dyn Write + Send>,
+    file: Option<AtomicFile>,
+}
+
+impl Output {
+    fn create(output_file: &Path) -> Result<Self> {
+        let (stream, file): (Box<dyn Write + Send>, _) = match output_file.to_str() {
+            Some("-") => (Box::new(stdout()), None),
+            _ => {
+                let file = AtomicFile::create(output_file)?;
+                (
+                    Box::new(BufWriter::new(file.file().try_clone()?)),
+                    Some(file),
+                )
+            }
+        };
+        Ok(Output {
+            path: output_file.to_path_buf(),
+            stream,
+            file,
+        })
+    }
+
+    /// Flush the output, replacing the output file with it
+    fn commit(mut self) -> Result<()> {
+        self.stream
+            .flush()
+            .output_context(|| format!("Error writing file {}", self.path.display()))?;
+        if let Some(file) = self.file {
+            file.commit()?;
+        }
+        Ok(())
+    }
+}
+
+/// A CSV file written along the output (e.g. of flagged names), which also
+/// replaces its file once committed
+struct CsvOutput {
+    writer: csv::Writer<File>,
+    file: AtomicFile,
+}
+
+impl CsvOutput {
+    fn create(path: &Path) -> Result<Self> {
+        let file = AtomicFile::create(path)?;
+        Ok(CsvOutput {
+            writer: csv::Writer::from_writer(file.file().try_clone()?),
+            file,
+        })
+    }
+
+    /// Reopen the CSV file of an interrupted run (see `AtomicFile::reopen`)
+    fn reopen(path: &Path, offset: u64) -> Result<Self> {
+        let file = AtomicFile::reopen(path, offset)?;
+        // The header was already written before the checkpoint
+        let writer = csv::WriterBuilder::new()
+            .has_headers(offset == 0)
+            .from_writer(file.file().try_clone()?);
+        Ok(CsvOutput { writer, file })
+    }
+
+    /// Keep the temporary file if the run fails (see `AtomicFile::keep_on_error`)
+    fn keep_on_error(self) -> Self {
+        CsvOutput {
+            file: self.file.keep_on_error(),
+            ..self
+        }
+    }
+
+    fn serialize(&mut self, record: impl serde::Serialize) -> Result<()> {
+        self.writer
+            .serialize(record)
+            .output_context(|| format!("Error writing file {}", self.file.path().display()))
+    }
+
+    /// Flush and sync the file, returning its size
+    fn sync(&mut self) -> Result<u64> {
+        self.writer
+            .flush()
+            .output_context(|| format!("Error writing file {}", self.file.path().display()))?;
+        let mut file = self.file.file();
+        file.sync_data()
+            .output_context(|| format!("Error writing file {}", self.file.path().display()))?;
+        Ok(file.stream_position()?)
+    }
+
+    /// Flush the file, replacing its output file with it
+    fn commit(mut self) -> Result<()> {
+        self.writer
+            .flush()
+            .output_context(|| format!("Error writing file {}", self.file.path().display()))?;
+        self.file.commit()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Read the selected XML entries of an indexed `.tar.gz` file, decompressing
+/// sections of it in parallel, and send them in archive order. Members up to
+/// the `resume_after` position are skipped.
+fn send_indexed_batches(
+    input_path: &Path,
+    index: &GzIndex,
+    orcid_filter: &OrcidFilter,
+    resume_after: Option<u64>,
+    tx: &Sender<Result<Batch>>,
+    recycled: &Receiver<Batch>,
+) {
+    let mut sections = index.sections();
+    // Sections that were completely converted before don't need decompressing
+    sections.retain(|section| resume_after.is_none_or(|after| section.end() > after));
+    // Decompress a few sections per thread at a time, to bound memory usage
+    for chunk in sections.chunks(rayon::current_num_threads() * 2) {
+        let batches: Vec<Result<Batch>> = chunk
+            .par_iter()
+            .map(|section| {
+                let mut batch = Batch::reuse(recycled);
+                let result = index.for_each_entry(input_path, section, |mut entry| {
+                    let position = section.start() + entry.raw_header_position();
+                    if resume_after.is_some_and(|after| position <= after) {
+                        return Ok(());
+                    }
+                    let path = entry.path()?;
+                    batch.last_member = Some(Member {
+                        path: path.display().to_string(),
+                        position,
+                    });
+                    // Skip unselected records without reading them
+                    if path.extension().and_then(OsStr::to_str) != Some("xml")
+                        || !orcid_filter.is_path_match(&path)
+                    {
+                        return Ok(());
+                    }
+                    let path = path.display().to_string();
+                    batch.push(&mut entry, &path)?;
+                    Ok(())
+                });
+                result
+                    .input_context(|| format!("Error reading file {}", input_path.display()))
+                    .map(|_| batch)
+            })
+            .collect();
+        for batch in batches {
+            let failed = batch.is_err();
+            if tx.send(batch).is_err() || failed {
+                return;
+            }
+        }
+    }
+}
+
+/// Read the selected XML entries of a `.tar.gz` file and send them in batches.
+/// Members up to the `resume_after` position are skipped.
+fn send_batches(
+    input_path: &Path,
+    orcid_filter: &OrcidFilter,
+    resume_after: Option<u64>,
+    tx: &Sender<Result<Batch>>,
+    recycled: &Receiver<Batch>,
+) -> Result<()> {
+    let file = File::open(input_path)
+        .input_context(|| format!("Error opening file {}", input_path.display()))?;
+    let mut archive = Archive::new(GzDecoder::new(file));
+    let entries = archive
+        .entries()
+        .input_context(|| format!("Error reading file {}", input_path.display()))?;
+
+    let mut batch = Batch::reuse(recycled);
+    for entry_result in entries {
+        let mut entry = entry_result
+            .input_context(|| format!("Error reading file {}", input_path.display()))?;
+        let position = entry.raw_header_position();
+        if resume_after.is_some_and(|after| position <= after) {
+            continue;
+        }
+        let path = entry
+            .path()
+            .input_context(|| format!("Error reading file {}", input_path.display()))?;
+        batch.last_member = Some(Member {
+            path: path.display().to_string(),
+            position,
+        });
+        // Skip unselected records without reading them
+        if path.extension().and_then(OsStr::to_str) != Some("xml")
+            || !orcid_filter.is_path_match(&path)
+        {
+            continue;
+        }
+        let path = path.display().to_string();
+        batch.push(&mut entry, &path)?;
+        if batch.entries.len() >= BATCH_SIZE
+            && tx
+                .send(Ok(std::mem::replace(&mut batch, Batch::reuse(recycled))))
+                .is_err()
+        {
+            return Ok(());
+        }
+    }
+    // Send remaining items
+    if batch.last_member.is_some() {
+        let _ = tx.send(Ok(batch));
+    }
+    Ok(())
+}
+
+/// Convert a `.tar.gz` file. With the index built by `index_tgz`, the archive
+/// is decompressed in parallel.
 pub fn convert_tgz(
-    input_file: &PathBuf,
-    output_file: &PathBuf,
+    input_file: &Path,
+    output_file: &Path,
     orgs_mappings_file: &Option<PathBuf>,
     filter_name: &Option<String>,
     format: &ConvertFormat,
+    options: &ConvertOptions,
+    index_file: &Option<PathBuf>,
 ) -> Result<()> {
-    let org_map = read_org_ids(orgs_mappings_file);
-
-    // Open the output stream with buffering
-    let mut out_stream: Box<dyn std::io::Write + Send> = match output_file.to_str() {
-        Some("-") => Box::new(BufWriter::new(stdout())),
-        _ => Box::new(BufWriter::new(
-            File::create(output_file)
-                .with_context(|| format!("Error opening file {}", input_file.display()))?,
-        )),
-    };
+    let converter = NameConverter::build(options, filter_name, orgs_mappings_file)?;
+    convert_tgz_with(&converter, input_file, output_file, format, index_file)
+}
 
-    let name_filter_re = match filter_name {
-        Some(re) => Regex::new(re.as_ref()).ok(),
-        _ => None,
+/// Convert a `.tar.gz` file with the given converter (see `convert_tgz`)
+fn convert_tgz_with(
+    converter: &NameConverter,
+    input_file: &Path,
+    output_file: &Path,
+    format: &ConvertFormat,
+    index_file: &Option<PathBuf>,
+) -> Result<()> {
+    let options = &converter.options;
+    let checkpoints = &options.checkpoints;
+    let mut checkpointer = None;
+    if checkpoints.is_enabled() {
+        if output_file.to_str() == Some("-") {
+            bail!("Checkpoints need an --output-file");
+        }
+        let shards = &options.shards;
+        if options.sort.sort_by.is_some() || shards.shard_size.is_some() || shards.shards.is_some()
+        {
+            bail!("Checkpoints can't be combined with --sort-by, --shard-size or --shards");
+        }
+        checkpointer = Some(Checkpointer::new(output_file, checkpoints));
+    }
+    let resumed = checkpoints
+        .resume
+        .then(|| {
+            Checkpoint::load(
+                &Checkpoint::path(output_file),
+                input_file,
+                &format.to_string(),
+                &converter.fingerprint(),
+            )
+        })
+        .transpose()?;
+
+    // Open the output stream with buffering, or the shards to split it into
+    let shards = Shards::create(output_file, &options.shards)?;
+    let mut output = ConvertOutput {
+        path: output_file.to_path_buf(),
+        stream: Box::new(std::io::sink()),
+        shards: None,
+        file: None,
+        offset: resumed.as_ref().map_or(0, |r| r.output_offset),
     };
+    match output_file.to_str() {
+        _ if shards.is_some() => output.shards = shards,
+        Some("-") => output.stream = Box::new(BufWriter::new(stdout())),
+        _ => {
+            // Keep the partial output of interrupted runs to resume them
+            let file = match &resumed {
+                Some(resumed) => AtomicFile::reopen(output_file, resumed.output_offset)?,
+                None if checkpointer.is_some() => AtomicFile::create(output_file)?.keep_on_error(),
+                None => AtomicFile::create(output_file)?,
+            };
+            output.stream = Box::new(BufWriter::new(file.file().try_clone()?));
+            output.file = Some(file);
+        }
+    };
+
+    let orcid_filter = OrcidFilter::new(&options.orcids)?;
+    let index = index_file
+        .as_deref()
+        .map(|index_file| GzIndex::open(index_file, input_file))
+        .transpose()?;
 
-    // Channel sends batches instead of individual items
-    let (tx, rx) = bounded::<Vec<String>>(8);
+    // Channel sends batches instead of individual items, and processed
+    // batches are sent back for reuse
+    let (tx, rx) = bounded::<Result<Batch>>(8);
+    let (recycle_tx, recycled) = bounded::<Batch>(16);
 
     // Spawn producer thread to read tar entries and batch them
-    let input_path = input_file.clone();
+    let input_path = input_file.to_path_buf();
+    let resume_after = resumed.as_ref().map(|r| r.last_member.position);
     let producer = thread::spawn(move || {
-        let file = File::open(&input_path).expect("Failed to open input file");
-        let mut archive = Archive::new(GzDecoder::new(file));
-        let entries = archive.entries().expect("Failed to read tar entries");
-
-        let mut batch = Vec::with_capacity(BATCH_SIZE);
-        for entry_result in entries {
-            let Ok(mut entry) = entry_result else { continue };
-            let Ok(path) = entry.path() else { continue };
-            if path.extension().and_then(OsStr::to_str) != Some("xml") {
-                continue;
-            }
-            let mut xml_content = String::new();
-            if entry.read_to_string(&mut xml_content).is_ok() {
-                batch.push(xml_content);
-                if batch.len() >= BATCH_SIZE {
-                    if tx.send(std::mem::take(&mut batch)).is_err() {
-                        break;
-                    }
-                    batch = Vec::with_capacity(BATCH_SIZE);
-                }
-            }
+        if let Some(index) = index {
+            send_indexed_batches(
+                &input_path,
+                &index,
+                &orcid_filter,
+                resume_after,
+                &tx,
+                &recycled,
+            );
+            return;
         }
-        // Send remaining items
-        if !batch.is_empty() {
-            let _ = tx.send(batch);
+        let result = send_batches(&input_path, &orcid_filter, resume_after, &tx, &recycled);
+        if let Err(err) = result {
+            let _ = tx.send(Err(err));
         }
     });
 
+    let mut review_writer = match &resumed {
+        Some(resumed) => reopen_csv_file(&options.name_checks.review_file, resumed.review_offset)?,
+        None => open_review_file(&options.name_checks)?,
+    };
+    let mut redirects_writer = match &resumed {
+        Some(resumed) => reopen_csv_file(&options.redirects_file, resumed.redirects_offset)?,
+        None => open_csv_file(&options.redirects_file)?,
+    };
+    if checkpointer.is_some() {
+        review_writer = review_writer.map(CsvOutput::keep_on_error);
+        redirects_writer = redirects_writer.map(CsvOutput::keep_on_error);
+    }
+
+    let sort = &options.sort;
+    let mut sorter = sort
+        .sort_by
+        .map(|_| {
+            let memory_budget = sort.sort_memory * 1024 * 1024;
+            ExternalSorter::<OutputLine>::new(memory_budget, sort.tmp_dir.as_deref(), false)
+        })
+        .transpose()?;
+    let now = match &resumed {
+        Some(resumed) => resumed.created.clone(),
+        None => options.created_timestamp(),
+    };
+    let es_index = options.es_index();
+
     // Process batches - use par_iter on each batch (no par_bridge!)
-    match format {
-        ConvertFormat::JSON => {
-            for batch in rx {
-                let results: Vec<_> = batch
-                    .par_iter()
-                    .filter_map(|xml| parse_xml(xml))
-                    .filter_map(|record| {
-                        let json = record_to_json(&record, &org_map).ok()?;
-                        if let Some(ref re) = name_filter_re {
-                            if !re.is_match(&json.name) {
-                                return None;
-                            }
-                        }
-                        serde_json::to_vec(&json).ok()
+    for batch in rx {
+        let mut batch = batch?;
+        let results: Vec<_> = batch
+            .xml_contents()
+            .filter_map(|xml| {
+                let record = parse_xml(xml)?;
+                let converted = match converter.convert_record(&record) {
+                    Ok(converted) => converted,
+                    Err(err) if err.is::<Skipped>() => return None,
+                    Err(err) => return Some(Err(err)),
+                };
+                let line = converted
+                    .json
+                    .map(|j| {
+                        Ok(OutputLine {
+                            orcid: record.identifier.path.to_string(),
+                            line: encode_line(&record, &j, format, &now, es_index)?,
+                        })
                     })
-                    .collect();
-                for bytes in results {
-                    out_stream.write_all(&bytes)?;
-                }
+                    .transpose();
+                Some(line.map(|line| (line, converted.review, converted.redirect)))
+            })
+            .collect::<Result<_>>()?;
+        let last_member = batch.last_member.take();
+        batch.clear();
+        let _ = recycle_tx.try_send(batch);
+        for (line, review, redirect) in results {
+            match (line, sorter.as_mut()) {
+                (Some(line), Some(sorter)) => sorter.push(line)?,
+                (Some(line), None) => output.write_line(&line)?,
+                (None, _) => {}
             }
-        }
-        ConvertFormat::InvenioRDMNames => {
-            let now = Utc::now().to_rfc3339();
-            let mut csv_writer = csv::WriterBuilder::new()
-                .has_headers(false)
-                .from_writer(&mut out_stream);
-
-            for batch in rx {
-                let results: Vec<_> = batch
-                    .par_iter()
-                    .filter_map(|xml| parse_xml(xml))
-                    .filter_map(|record| {
-                        record_to_row(&record, &org_map, &now, &name_filter_re).ok()
-                    })
-                    .collect();
-                for row in results {
-                    csv_writer.serialize(&row)?;
-                }
+            if let (Some(writer), Some(review)) = (review_writer.as_mut(), review) {
+                writer.serialize(review)?;
+            }
+            if let (Some(writer), Some(redirect)) = (redirects_writer.as_mut(), redirect) {
+                writer.serialize(redirect)?;
             }
         }
-    };
+
+        // All records of the batch are written, so it's safe to resume after it
+        let (Some(checkpointer), Some(last_member)) = (checkpointer.as_mut(), last_member) else {
+            continue;
+        };
+        if !checkpointer.is_due() {
+            continue;
+        }
+        let checkpoint = Checkpoint {
+            input_file: input_file.to_path_buf(),
+            input_size: fs::metadata(input_file)
+                .input_context(|| format!("Error reading file {}", input_file.display()))?
+                .len(),
+            format: format.to_string(),
+            options_fingerprint: converter.fingerprint(),
+            created: now.clone(),
+            last_member,
+            output_offset: output.sync()?,
+            review_offset: sync_csv_file(review_writer.as_mut())?,
+            redirects_offset: sync_csv_file(redirects_writer.as_mut())?,
+        };
+        checkpointer.save(&checkpoint)?;
+    }
+    if let Some(sorter) = sorter {
+        for line in sorter.into_sorted_iter()? {
+            output.write_line(&line?)?;
+        }
+    }
+    output.finish()?;
+    if let Some(writer) = review_writer {
+        writer.commit()?;
+    }
+    if let Some(writer) = redirects_writer {
+        writer.commit()?;
+    }
 
     // Wait for producer to finish
-    producer.join().expect("Producer thread panicked");
+    if producer.join().is_err() {
+        bail!("Error reading file {}", input_file.display());
+    }
+
+    // The conversion is complete, so there's nothing left to resume
+    if let Some(checkpointer) = checkpointer {
+        checkpointer.remove()?;
+    }
 
     Ok(())
 }
 
 pub fn convert_xml(
-    input_file: &PathBuf,
-    output_file: &PathBuf,
+    input_file: &Path,
+    output_file: &Path,
     orgs_mappings_file: &Option<PathBuf>,
+    filter_name: &Option<String>,
     format: &ConvertFormat,
+    options: &ConvertOptions,
 ) -> Result<()> {
-    let org_map = read_org_ids(orgs_mappings_file);
-    let xml = fs::read_to_string(input_file).expect("Failed to read XML file");
-    let rd = &mut Deserializer::from_str(&xml);
-    let record = serde_path_to_error::deserialize(rd)
-        .with_context(|| "Error parsing XML content".to_string())?;
-
-    let mut out_stream = match output_file.to_str() {
-        Some("-") => Box::new(stdout()) as Box<dyn std::io::Write>,
-        _ => Box::new(
-            File::create(output_file)
-                .with_context(|| format!("Error opening file {}", input_file.display()))?,
-        ),
-    };
+    let converter = NameConverter::build(options, filter_name, orgs_mappings_file)?;
+    convert_xml_with(&converter, input_file, output_file, format)
+}
 
-    match format {
-        ConvertFormat::InvenioRDMNames => {
-            let now = Utc::now().to_rfc3339();
-            let row =
-                record_to_row(&record, &org_map, &now, &None).expect("Failed to convert to CSV");
-            let mut writer = csv::WriterBuilder::new()
-                .has_headers(false)
-                .from_writer(out_stream);
-            writer.serialize(row).unwrap()
+/// Convert an XML file with the given converter (see `convert_xml`)
+fn convert_xml_with(
+    converter: &NameConverter,
+    input_file: &Path,
+    output_file: &Path,
+    format: &ConvertFormat,
+) -> Result<()> {
+    let options = &converter.options;
+    if options.checkpoints.is_enabled() {
+        bail!("Checkpoints are only supported for .tar.gz input files");
+    }
+    if options.shards.shard_size.is_some() || options.shards.shards.is_some() {
+        bail!("Sharded output is only supported for .tar.gz input files");
+    }
+    let orcid_filter = OrcidFilter::new(&options.orcids)?;
+    let mut xml = fs::read_to_string(input_file)
+        .input_context(|| format!("Error reading file {}", input_file.display()))?;
+    let record =
+        Record::from_xml_mut(&mut xml).parse_context(|| "Error parsing XML content".to_string())?;
+
+    let mut output = Output::create(output_file)?;
+    // Only committed once the record is converted, so failed (e.g. deprecated)
+    // records leave no redirect behind
+    let redirects_file = open_csv_file(&options.redirects_file)?;
+
+    // As in .tar.gz files, records that are filtered out give no output
+    let converted = if orcid_filter.is_match(&record.identifier.path) {
+        let status = record.status();
+        if status != RecordStatus::Active {
+            bail!("Record {} is {}", record.identifier.path, status);
         }
-        ConvertFormat::JSON => {
-            let json = record_to_json(&record, &org_map).expect("Failed to convert to JSON");
-            serde_json::to_writer_pretty(&mut out_stream, &json)
-                .with_context(|| "Error writing JSON".to_string())?;
+        match converter.convert_record(&record) {
+            Err(err) if err.downcast_ref::<Skipped>().is_some_and(|s| s.filtered) => {
+                Converted::default()
+            }
+            result => result?,
         }
+    } else {
+        Converted::default()
     };
+    if let Some(review) = converted.review {
+        if let Some(mut writer) = open_review_file(&options.name_checks)? {
+            writer.serialize(&review)?;
+            writer.commit()?;
+        }
+        if review.rejected {
+            bail!(
+                "Name {:?} rejected by name checks: {}",
+                review.name,
+                review.checks
+            );
+        }
+    }
+    if let Some(json) = converted.json {
+        match format {
+            ConvertFormat::JSON => {
+                serde_json::to_writer_pretty(&mut output, &json)
+                    .output_context(|| "Error writing JSON".to_string())?;
+            }
+            _ => {
+                let now = options.created_timestamp();
+                let line = encode_line(&record, &json, format, &now, options.es_index())?;
+                output
+                    .write_all(line.as_bytes())
+                    .output_context(|| "Error writing output".to_string())?;
+            }
+        };
+    }
+    output.commit()?;
+    if let Some(writer) = redirects_file {
+        writer.commit()?;
+    }
     Ok(())
 }
 
-fn read_org_ids(orgs_mappings_file: &Option<PathBuf>) -> OrgMap {
+fn read_org_ids(orgs_mappings_file: &Option<PathBuf>) -> Result<OrgMap> {
     let mut org_map = OrgMap::new();
-    if let Some(orgs_mappings_file) = orgs_mappings_file {
-        if let Ok(file) = File::open(orgs_mappings_file) {
-            let mut reader = csv::ReaderBuilder::new()
-                .has_headers(false)
-                .from_reader(file);
-            for result in reader.deserialize() {
-                let (scheme, identifier, ror_id): (String, String, String) =
-                    result.expect("Failed to parse org IDs file");
-                org_map.insert(ExtractedIdentifier { scheme, identifier }, ror_id);
-            }
+    if let Some(path) = orgs_mappings_file {
+        let file =
+            File::open(path).input_context(|| format!("Error opening file {}", path.display()))?;
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(file);
+        for result in reader.deserialize() {
+            let (scheme, identifier, ror_id): (String, String, String) =
+                result.parse_context(|| format!("Error parsing file {}", path.display()))?;
+            org_map.insert(ExtractedIdentifier { scheme, identifier }, ror_id);
         }
     }
-    org_map
+    Ok(org_map)
 }
 
-#[derive(Debug, Hash, Eq, PartialEq, serde::Serialize)]
+#[derive(
+    Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd, serde::Serialize, serde::Deserialize,
+)]
 struct ExtractedIdentifier {
     scheme: String,
     identifier: String,
 }
 
-fn collect_org_ids(record: Record) -> HashSet<ExtractedIdentifier> {
+impl MemorySize for ExtractedIdentifier {
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.scheme.len() + self.identifier.len()
+    }
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct ExtractOptions {
+    /// Memory budget (in MB, at least 1) for de-duplicating extracted
+    /// identifiers. When set, identifiers are sorted and de-duplicated on disk
+    /// instead of in memory.
+    #[arg(
+        long = "dedup-memory",
+        value_name = "MB",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub dedup_memory: Option<usize>,
+
+    /// Directory for temporary files (defaults to the system temporary directory)
+    #[arg(long = "tmp-dir")]
+    pub tmp_dir: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub orcids: OrcidSelection,
+}
+
+fn collect_org_ids(record: &Record) -> HashSet<ExtractedIdentifier> {
     record
         .activities
         .employments
         .employment
-        .unwrap_or_default()
         .iter()
+        .flatten()
         .filter_map(|a| {
             a.employment
                 .organization
@@ -511,74 +1562,494 @@ fn collect_org_ids(record: Record) -> HashSet<ExtractedIdentifier> {
         .collect()
 }
 
-pub fn extract_xml(
-    input_file: &PathBuf,
-    output_file: &PathBuf,
+fn collect_person_ids(record: &Record) -> HashSet<ExtractedIdentifier> {
+    let external_identifiers = record.person.external_identifiers.as_ref();
+    external_identifiers
+        .and_then(|e| e.identifiers.as_ref())
+        .into_iter()
+        .flatten()
+        .map(|id| ExtractedIdentifier {
+            scheme: id.id_type.trim().to_string(),
+            identifier: id.value.trim().to_string(),
+        })
+        .collect()
+}
+
+/// Build identifiers of a fixed `scheme` out of (non-empty) values
+fn scheme_identifiers<'a>(
+    scheme: &str,
+    values: impl Iterator<Item = &'a str>,
+) -> HashSet<ExtractedIdentifier> {
+    values
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|v| ExtractedIdentifier {
+            scheme: scheme.to_string(),
+            identifier: v.nfc().to_string(),
+        })
+        .collect()
+}
+
+fn collect_researcher_urls(record: &Record) -> HashSet<ExtractedIdentifier> {
+    let urls = record.person.researcher_urls.as_ref();
+    let urls = urls.and_then(|u| u.urls.as_ref()).into_iter().flatten();
+    scheme_identifiers("url", urls.map(|u| u.url.as_ref()))
+}
+
+fn collect_keywords(record: &Record) -> HashSet<ExtractedIdentifier> {
+    let keywords = record.person.keywords.as_ref();
+    let keywords = keywords
+        .and_then(|k| k.keyword.as_ref())
+        .into_iter()
+        .flatten();
+    scheme_identifiers("keyword", keywords.map(|k| k.content.as_ref()))
+}
+
+fn collect_email_domains(record: &Record) -> HashSet<ExtractedIdentifier> {
+    let emails = record.person.emails.as_ref();
+    let domains: Vec<String> = emails
+        .and_then(|e| e.email.as_ref())
+        .into_iter()
+        .flatten()
+        .filter(|e| e.visibility.as_deref() == Some("public"))
+        .filter_map(|e| e.email.rsplit_once('@'))
+        .map(|(_, domain)| domain.to_lowercase())
+        .collect();
+    scheme_identifiers("email-domain", domains.iter().map(String::as_str))
+}
+
+fn collect_countries(record: &Record) -> HashSet<ExtractedIdentifier> {
+    let addresses = record.person.addresses.as_ref();
+    let addresses = addresses
+        .and_then(|a| a.address.as_ref())
+        .into_iter()
+        .flatten();
+    scheme_identifiers("country", addresses.map(|a| a.country.as_ref()))
+}
+
+/// Collect the unique identifiers of a record for the given (non-aggregated) format
+fn collect_identifiers(record: &Record, format: &ExtractFormat) -> HashSet<ExtractedIdentifier> {
+    match format {
+        ExtractFormat::OrgIDs => collect_org_ids(record),
+        ExtractFormat::PersonIDs => collect_person_ids(record),
+        ExtractFormat::ResearcherURLs => collect_researcher_urls(record),
+        ExtractFormat::Keywords => collect_keywords(record),
+        ExtractFormat::EmailDomains => collect_email_domains(record),
+        ExtractFormat::Countries => collect_countries(record),
+        ExtractFormat::OrgStats | ExtractFormat::OrgStatsCSV => HashSet::new(),
+    }
+}
+
+/// Number of most common names/locations reported per organization identifier
+const ORG_STATS_TOP_N: usize = 5;
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd, serde::Serialize)]
+struct OrgLocation {
+    city: Option<String>,
+    region: Option<String>,
+    country: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct OrgStatsAccumulator {
+    orcids: usize,
+    current_employments: usize,
+    past_employments: usize,
+    names: HashMap<String, usize>,
+    locations: HashMap<OrgLocation, usize>,
+}
+
+type OrgStatsMap = HashMap<ExtractedIdentifier, OrgStatsAccumulator>;
+
+#[derive(Debug, serde::Serialize)]
+struct Counted<T> {
+    value: T,
+    count: usize,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct OrgStats {
+    scheme: String,
+    identifier: String,
+    orcids: usize,
+    current_employments: usize,
+    past_employments: usize,
+    names: Vec<Counted<String>>,
+    locations: Vec<Counted<OrgLocation>>,
+}
+
+/// Flat CSV representation of `OrgStats`, keeping only the most common name/location
+#[derive(serde::Serialize)]
+struct OrgStatsRow<'a> {
+    scheme: &'a str,
+    identifier: &'a str,
+    orcids: usize,
+    current_employments: usize,
+    past_employments: usize,
+    name: Option<&'a str>,
+    city: Option<&'a str>,
+    region: Option<&'a str>,
+    country: Option<&'a str>,
+}
+
+impl<'a> From<&'a OrgStats> for OrgStatsRow<'a> {
+    fn from(stats: &'a OrgStats) -> Self {
+        let location = stats.locations.first().map(|l| &l.value);
+        OrgStatsRow {
+            scheme: &stats.scheme,
+            identifier: &stats.identifier,
+            orcids: stats.orcids,
+            current_employments: stats.current_employments,
+            past_employments: stats.past_employments,
+            name: stats.names.first().map(|n| n.value.as_str()),
+            city: location.and_then(|l| l.city.as_deref()),
+            region: location.and_then(|l| l.region.as_deref()),
+            country: location.and_then(|l| l.country.as_deref()),
+        }
+    }
+}
+
+fn collect_org_stats(record: &Record, stats: &mut OrgStatsMap) {
+    // ORCiDs are counted once per organization, even with multiple employments
+    let mut seen = HashSet::new();
+    let employments = record.activities.employments.employment.iter().flatten();
+    for a in employments {
+        let organization = &a.employment.organization;
+        let Some(id) = &organization.identifier else {
+            continue;
+        };
+        let key = ExtractedIdentifier {
+            scheme: id.source.to_string(),
+            identifier: id.identifier.to_string(),
+        };
+        let entry = stats.entry(key.clone()).or_default();
+        if seen.insert(key) {
+            entry.orcids += 1;
+        }
+        match a.employment.end {
+            Some(_) => entry.past_employments += 1,
+            None => entry.current_employments += 1,
+        }
+        *entry
+            .names
+            .entry(organization.name.nfc().to_string())
+            .or_default() += 1;
+        if let Some(address) = &organization.address {
+            let location = OrgLocation {
+                city: address.city.as_ref().map(|c| c.nfc().to_string()),
+                region: address.region.as_ref().map(|r| r.nfc().to_string()),
+                country: address.country.as_deref().map(str::to_string),
+            };
+            *entry.locations.entry(location).or_default() += 1;
+        }
+    }
+}
+
+/// Sort values by descending count (ties broken by value) and keep the top ones
+fn most_common<T: Ord>(counts: HashMap<T, usize>) -> Vec<Counted<T>> {
+    let mut counted: Vec<_> = counts
+        .into_iter()
+        .map(|(value, count)| Counted { value, count })
+        .collect();
+    counted.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+    counted.truncate(ORG_STATS_TOP_N);
+    counted
+}
+
+fn write_org_stats<W: Write>(
+    stats: OrgStatsMap,
     format: &ExtractFormat,
+    mut output: W,
 ) -> Result<()> {
-    let xml = fs::read_to_string(input_file).expect("Failed to read XML file");
-    let rd = &mut Deserializer::from_str(&xml);
-    let record: Record = serde_path_to_error::deserialize(rd)
-        .with_context(|| "Error parsing XML content".to_string())?;
-
-    let mut out_stream = match output_file.to_str() {
-        Some("-") => Box::new(stdout()) as Box<dyn std::io::Write>,
-        _ => Box::new(
-            File::create(output_file)
-                .with_context(|| format!("Error opening file {}", input_file.display()))?,
-        ),
-    };
+    let mut stats: Vec<OrgStats> = stats
+        .into_iter()
+        .map(|(key, acc)| OrgStats {
+            scheme: key.scheme,
+            identifier: key.identifier,
+            orcids: acc.orcids,
+            current_employments: acc.current_employments,
+            past_employments: acc.past_employments,
+            names: most_common(acc.names),
+            locations: most_common(acc.locations),
+        })
+        .collect();
+    // Most referenced organizations first, which is the order curation happens in
+    stats.sort_by(|a, b| {
+        b.orcids
+            .cmp(&a.orcids)
+            .then_with(|| a.scheme.cmp(&b.scheme))
+            .then_with(|| a.identifier.cmp(&b.identifier))
+    });
 
     match format {
-        ExtractFormat::OrgIDs => {
-            let identifiers = collect_org_ids(record);
-            writeln!(
-                out_stream,
-                "{}",
-                serde_json::to_string_pretty(&identifiers)?
-            )
-            .with_context(|| "Error writing JSON".to_string())?;
+        ExtractFormat::OrgStatsCSV => {
+            let mut writer = csv::Writer::from_writer(output);
+            for s in &stats {
+                writer.serialize(OrgStatsRow::from(s))?;
+            }
+            writer
+                .flush()
+                .output_context(|| "Error writing CSV".to_string())?;
+        }
+        _ => {
+            for s in &stats {
+                writeln!(output, "{}", serde_json::to_string(s)?)
+                    .output_context(|| "Error writing JSON".to_string())?;
+            }
         }
     }
     Ok(())
 }
 
+pub fn extract_xml(
+    input_file: &Path,
+    output_file: &Path,
+    format: &ExtractFormat,
+    options: &ExtractOptions,
+) -> Result<()> {
+    let orcid_filter = OrcidFilter::new(&options.orcids)?;
+    let mut xml = fs::read_to_string(input_file)
+        .input_context(|| format!("Error reading file {}", input_file.display()))?;
+    let record =
+        Record::from_xml_mut(&mut xml).parse_context(|| "Error parsing XML content".to_string())?;
+
+    let mut output = Output::create(output_file)?;
+    // As in .tar.gz files, records that are filtered out give no output
+    if !orcid_filter.is_match(&record.identifier.path) {
+        return output.commit();
+    }
+    let status = record.status();
+    if status != RecordStatus::Active {
+        bail!("Record {} is {}", record.identifier.path, status);
+    }
+
+    match format {
+        ExtractFormat::OrgStats | ExtractFormat::OrgStatsCSV => {
+            let mut stats = OrgStatsMap::new();
+            collect_org_stats(&record, &mut stats);
+            write_org_stats(stats, format, &mut output)?;
+        }
+        _ => {
+            let identifiers = collect_identifiers(&record, format);
+            writeln!(output, "{}", serde_json::to_string_pretty(&identifiers)?)
+                .output_context(|| "Error writing JSON".to_string())?;
+        }
+    }
+    output.commit()
+}
+
 pub fn extract_tgz(
-    input_file: &PathBuf,
-    output_file: &PathBuf,
+    input_file: &Path,
+    output_file: &Path,
     format: &ExtractFormat,
+    options: &ExtractOptions,
 ) -> Result<()> {
     // Open the input .tar.gz
     let file = File::open(input_file)
-        .with_context(|| format!("Error opening file {}", input_file.display()))?;
+        .input_context(|| format!("Error opening file {}", input_file.display()))?;
     let mut archive = Archive::new(GzDecoder::new(file));
-    let records = iter_records(archive.entries().unwrap());
-
-    // Open the output CSV writer
-    let mut out_stream = match output_file.to_str() {
-        Some("-") => Box::new(stdout()) as Box<dyn std::io::Write>,
-        _ => Box::new(
-            File::create(output_file)
-                .with_context(|| format!("Error opening file {}", input_file.display()))?,
-        ),
-    };
+    let orcid_filter = OrcidFilter::new(&options.orcids)?;
+    let entries = archive
+        .entries()
+        .input_context(|| format!("Error reading file {}", input_file.display()))?;
+
+    // Open the output
+    let mut output = Output::create(output_file)?;
 
     match format {
-        ExtractFormat::OrgIDs => {
-            let mut identifiers = HashSet::<ExtractedIdentifier>::new();
-            for r in records {
-                let org_ids = collect_org_ids(r);
-                // Write the org IDs that are not already in the set
-                for i in &org_ids {
-                    if !identifiers.contains(i) {
-                        writeln!(out_stream, "{}", serde_json::to_string(i)?)
-                            .with_context(|| "Error writing JSON".to_string())?;
+        ExtractFormat::OrgStats | ExtractFormat::OrgStatsCSV => {
+            let mut stats = OrgStatsMap::new();
+            for_each_record(input_file, entries, &orcid_filter, |r| {
+                collect_org_stats(r, &mut stats);
+                Ok(())
+            })?;
+            write_org_stats(stats, format, &mut output)?;
+        }
+        _ => {
+            if let Some(dedup_memory) = options.dedup_memory {
+                let memory_budget = dedup_memory * 1024 * 1024;
+                let mut sorter =
+                    ExternalSorter::new(memory_budget, options.tmp_dir.as_deref(), true)?;
+                for_each_record(input_file, entries, &orcid_filter, |r| {
+                    for i in collect_identifiers(r, format) {
+                        sorter.push(i)?;
                     }
+                    Ok(())
+                })?;
+                for i in sorter.into_sorted_iter()? {
+                    writeln!(output, "{}", serde_json::to_string(&i?)?)
+                        .output_context(|| "Error writing JSON".to_string())?;
                 }
-                identifiers.extend(org_ids);
+            } else {
+                let mut identifiers = HashSet::<ExtractedIdentifier>::new();
+                for_each_record(input_file, entries, &orcid_filter, |r| {
+                    let extracted = collect_identifiers(r, format);
+                    // Write the identifiers that are not already in the set
+                    for i in &extracted {
+                        if !identifiers.contains(i) {
+                            writeln!(output, "{}", serde_json::to_string(i)?)
+                                .output_context(|| "Error writing JSON".to_string())?;
+                        }
+                    }
+                    identifiers.extend(extracted);
+                    Ok(())
+                })?;
             }
         }
     }
 
+    output.commit()
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum GetFormat {
+    /// The record's XML, as stored in the archive
+    XML,
+    /// The converted record, as with `convert --format json`
+    JSON,
+}
+
+/// The options of `convert` that apply to a single record, i.e. to
+/// `get --format json`
+#[derive(Args, Debug, Clone, Default)]
+pub struct GetOptions {
+    /// Regular expression that (display) names must match
+    #[arg(long = "filter-name", default_value = DEFAULT_NAME_FILTER_REGEX)]
+    pub filter_name: Option<String>,
+
+    /// Person external identifier type to include in the name identifiers (e.g.
+    /// "Scopus Author ID"), optionally mapped to a scheme name ("ResearcherID=wos").
+    /// Can be repeated; types that are not listed are left out.
+    #[arg(long = "person-id", value_name = "TYPE[=SCHEME]")]
+    pub person_ids: Vec<PersonIdScheme>,
+
+    /// Use the credit name (i.e. published name) as the display name when present
+    #[arg(long = "use-credit-name")]
+    pub use_credit_name: bool,
+
+    /// Include other names (and the credit name) as `alternative_names`
+    #[arg(long = "other-names")]
+    pub other_names: bool,
+
+    #[command(flatten)]
+    pub name_policy: NamePolicy,
+
+    #[command(flatten)]
+    pub name_checks: NameChecks,
+
+    #[command(flatten)]
+    pub exclude_names: NameExclusions,
+
+    #[command(flatten)]
+    pub scripts: ScriptOptions,
+
+    #[command(flatten)]
+    pub affiliations: AffiliationFilter,
+}
+
+impl From<&GetOptions> for ConvertOptions {
+    fn from(options: &GetOptions) -> Self {
+        ConvertOptions {
+            person_ids: options.person_ids.clone(),
+            use_credit_name: options.use_credit_name,
+            other_names: options.other_names,
+            name_policy: options.name_policy.clone(),
+            name_checks: options.name_checks.clone(),
+            exclude_names: options.exclude_names.clone(),
+            scripts: options.scripts.clone(),
+            affiliations: options.affiliations.clone(),
+            ..ConvertOptions::default()
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct IndexOptions {
+    /// Amount of decompressed data (in MB) between checkpoints. Smaller spans
+    /// make lookups faster, at the cost of a larger index.
+    #[arg(long = "span", value_name = "MB", default_value_t = 16)]
+    pub span: u64,
+
+    /// Memory budget (in MB) for sorting the table of records
+    #[arg(long = "sort-memory", value_name = "MB", default_value_t = 256)]
+    pub sort_memory: usize,
+
+    /// Directory for temporary files (defaults to the system temporary directory)
+    #[arg(long = "tmp-dir")]
+    pub tmp_dir: Option<PathBuf>,
+}
+
+/// Default path of the index of a `.tar.gz` file, i.e. `<input file>.idx`
+pub fn default_index_file(input_file: &Path) -> PathBuf {
+    let mut path = input_file.as_os_str().to_owned();
+    path.push(".idx");
+    PathBuf::from(path)
+}
+
+/// Build a random access index for a `.tar.gz` file
+pub fn index_tgz(input_file: &Path, index_file: &Path, options: &IndexOptions) -> Result<()> {
+    let stats = gz_index::build_index(
+        input_file,
+        index_file,
+        options.span * 1024 * 1024,
+        options.sort_memory * 1024 * 1024,
+        options.tmp_dir.as_deref(),
+    )?;
+    eprintln!(
+        "Indexed {} records ({} checkpoints) in {}",
+        stats.members,
+        stats.checkpoints,
+        index_file.display()
+    );
     Ok(())
 }
+
+/// Output a single record of an indexed `.tar.gz` file
+pub fn get_record(
+    input_file: &Path,
+    index_file: &Path,
+    orcid: &str,
+    output_file: &Path,
+    orgs_mappings_file: &Option<PathBuf>,
+    format: &GetFormat,
+    options: &GetOptions,
+) -> Result<()> {
+    let mut index = GzIndex::open(index_file, input_file)?;
+    let Some(member) = index.find_member(orcid)? else {
+        bail!("Record {} not found in {}", orcid, input_file.display());
+    };
+    let xml = index.read_member_data(input_file, &member)?;
+
+    let mut output = Output::create(output_file)?;
+
+    match format {
+        GetFormat::XML => output
+            .write_all(&xml)
+            .output_context(|| "Error writing XML".to_string())?,
+        GetFormat::JSON => {
+            let mut xml = String::from_utf8(xml)
+                .parse_context(|| "Invalid UTF-8 in XML content".to_string())?;
+            let record = Record::from_xml_mut(&mut xml)
+                .parse_context(|| "Error parsing XML content".to_string())?;
+            let status = record.status();
+            if status != RecordStatus::Active {
+                bail!("Record {} is {}", record.identifier.path, status);
+            }
+            let converter =
+                NameConverter::build(&options.into(), &options.filter_name, orgs_mappings_file)?;
+            let converted = converter.convert_record(&record)?;
+            let Some(json) = converted.json else {
+                let checks = converted.review.map(|review| review.checks);
+                bail!(
+                    "Record {} rejected by the name checks ({})",
+                    record.identifier.path,
+                    checks.unwrap_or_default()
+                );
+            };
+            serde_json::to_writer_pretty(&mut output, &json)
+                .output_context(|| "Error writing JSON".to_string())?;
+            writeln!(output).output_context(|| "Error writing JSON".to_string())?;
+        }
+    }
+    output.commit()
+}
@@ -1,8 +1,10 @@
 use anyhow::{bail, Result};
 use orcid_data_toolkit::{
-    convert_tgz, convert_xml, extract_tgz, extract_xml, ConvertFormat, ExtractFormat,
+    convert_tgz, convert_xml, default_index_file, extract_tgz, extract_xml, get_record, index_tgz,
+    ConvertFormat, ConvertOptions, ErrorKind, ExtractFormat, ExtractOptions, GetFormat, GetOptions,
+    IndexOptions, DEFAULT_NAME_FILTER_REGEX,
 };
-use std::{ffi::OsStr, path::PathBuf};
+use std::{ffi::OsStr, path::PathBuf, process::ExitCode};
 
 use clap::{Parser, Subcommand};
 
@@ -13,8 +15,8 @@ struct Cli {
     command: Commands,
 }
 
-const DEFAULT_NAME_FILTER_REGEX: &str = r"^[\p{L} ,.'’`´\-\(\)]+$";
-
+// Only parsed once, so the size of the options doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     Convert {
@@ -34,8 +36,16 @@ enum Commands {
         #[arg(long = "orgs-mapping")]
         orgs_mappings_file: Option<PathBuf>,
 
+        /// Regular expression that (display) names must match
         #[arg(long = "filter-name", default_value=DEFAULT_NAME_FILTER_REGEX)]
         filter_name: Option<String>,
+
+        /// Path to the index built by `index`, to decompress the input in parallel
+        #[arg(long = "index-file")]
+        index_file: Option<PathBuf>,
+
+        #[command(flatten)]
+        options: ConvertOptions,
     },
 
     Extract {
@@ -50,27 +60,97 @@ enum Commands {
         /// Extract format
         #[arg(value_enum, short, long, default_value_t=ExtractFormat::OrgIDs)]
         format: ExtractFormat,
+
+        #[command(flatten)]
+        options: ExtractOptions,
+    },
+
+    /// Build a random access index for the ORCiD public data file
+    Index {
+        /// Path to the ORCiD public data file
+        #[arg(short, long)]
+        input_file: PathBuf,
+
+        /// Path to the index file (defaults to the input file path plus `.idx`)
+        #[arg(long = "index-file")]
+        index_file: Option<PathBuf>,
+
+        #[command(flatten)]
+        options: IndexOptions,
+    },
+
+    /// Output a single record, using the index built by `index`
+    Get {
+        /// ORCiD iD of the record, e.g. 0000-0002-5082-6404
+        orcid: String,
+
+        /// Path to the ORCiD public data file
+        #[arg(short, long)]
+        input_file: PathBuf,
+
+        /// Path to the index file (defaults to the input file path plus `.idx`)
+        #[arg(long = "index-file")]
+        index_file: Option<PathBuf>,
+
+        /// Path to where to output the record,
+        #[arg(short, long, default_value = "-")]
+        output_file: PathBuf,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t=GetFormat::XML)]
+        format: GetFormat,
+
+        /// Path to Organization ID CSV mappings file
+        #[arg(long = "orgs-mapping")]
+        orgs_mappings_file: Option<PathBuf>,
+
+        #[command(flatten)]
+        options: GetOptions,
     },
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let cli = Cli::parse();
+    match run(&cli.command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            // Distinguish input, parse and output failures
+            ExitCode::from(ErrorKind::of(&err).map_or(1, ErrorKind::exit_code))
+        }
+    }
+}
 
-    match &cli.command {
+fn run(command: &Commands) -> Result<()> {
+    match command {
         Commands::Convert {
             input_file,
             output_file,
             orgs_mappings_file,
             filter_name,
+            index_file,
             format,
+            options,
         } => match input_file.extension().and_then(OsStr::to_str) {
-            Some("xml") => convert_xml(input_file, output_file, orgs_mappings_file, format),
+            Some("xml") if index_file.is_some() => {
+                bail!("--index-file is only supported for .tar.gz input files")
+            }
+            Some("xml") => convert_xml(
+                input_file,
+                output_file,
+                orgs_mappings_file,
+                filter_name,
+                format,
+                options,
+            ),
             Some("gz") => convert_tgz(
                 input_file,
                 output_file,
                 orgs_mappings_file,
                 filter_name,
                 format,
+                options,
+                index_file,
             ),
             _ => bail!("Unsupported file extension"),
         },
@@ -78,10 +158,43 @@ fn main() -> Result<()> {
             input_file,
             output_file,
             format,
+            options,
         } => match input_file.extension().and_then(OsStr::to_str) {
-            Some("xml") => extract_xml(input_file, output_file, format),
-            Some("gz") => extract_tgz(input_file, output_file, format),
+            Some("xml") => extract_xml(input_file, output_file, format, options),
+            Some("gz") => extract_tgz(input_file, output_file, format, options),
             _ => bail!("Unsupported file extension"),
         },
+        Commands::Index {
+            input_file,
+            index_file,
+            options,
+        } => {
+            let index_file = index_file
+                .clone()
+                .unwrap_or_else(|| default_index_file(input_file));
+            index_tgz(input_file, &index_file, options)
+        }
+        Commands::Get {
+            orcid,
+            input_file,
+            index_file,
+            output_file,
+            format,
+            orgs_mappings_file,
+            options,
+        } => {
+            let index_file = index_file
+                .clone()
+                .unwrap_or_else(|| default_index_file(input_file));
+            get_record(
+                input_file,
+                &index_file,
+                orcid,
+                output_file,
+                orgs_mappings_file,
+                format,
+                options,
+            )
+        }
     }
 }
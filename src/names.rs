@@ -0,0 +1,233 @@
+//! Policies for composing and formatting person names.
+
+use std::{fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use regex::{Regex, RegexSet};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::error::ErrorContext;
+
+/// Default display name template, e.g. "Ioannidis, Alex"
+pub const DEFAULT_NAME_TEMPLATE: &str = "{family}, {given}";
+
+/// Default name filter, i.e. letters, spaces and common punctuation
+pub const DEFAULT_NAME_FILTER_REGEX: &str = r"^[\p{L} ,.'’`´\-\(\)]+$";
+
+/// Which name part a single (given or family only) name is stored in
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Mononym {
+    Given,
+    Family,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum NameCase {
+    /// Keep names as entered in ORCiD
+    Preserve,
+    /// Capitalize the first letter of each word, e.g. "ALEX" -> "Alex"
+    Title,
+    Lower,
+    Upper,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct NamePolicy {
+    /// Display name template, using the `{given}` and `{family}` placeholders
+    #[arg(
+        long = "name-template",
+        default_value = DEFAULT_NAME_TEMPLATE,
+        value_parser = parse_template,
+    )]
+    pub template: String,
+
+    /// Name part to use when only a given or a family name is present
+    #[arg(long = "mononym", value_enum, default_value_t = Mononym::Family)]
+    pub mononym: Mononym,
+
+    /// Case normalization applied to all name values
+    #[arg(long = "name-case", value_enum, default_value_t = NameCase::Preserve)]
+    pub case: NameCase,
+
+    /// Trim names and collapse consecutive whitespace into a single space
+    #[arg(long = "collapse-whitespace")]
+    pub collapse_whitespace: bool,
+}
+
+impl Default for NamePolicy {
+    fn default() -> Self {
+        NamePolicy {
+            template: DEFAULT_NAME_TEMPLATE.to_string(),
+            mononym: Mononym::Family,
+            case: NameCase::Preserve,
+            collapse_whitespace: false,
+        }
+    }
+}
+
+pub(crate) fn parse_template(template: &str) -> Result<String, String> {
+    if !template.contains("{given}") && !template.contains("{family}") {
+        return Err("Template must contain {given} and/or {family}".to_string());
+    }
+    Ok(template.to_string())
+}
+
+/// The name values of a `NameJson`
+#[derive(Debug, PartialEq)]
+pub(crate) struct ComposedName {
+    pub given_name: String,
+    pub family_name: String,
+    pub name: String,
+}
+
+impl NamePolicy {
+    /// Normalize a name value (NFC, whitespace and case)
+    pub(crate) fn normalize(&self, value: &str) -> String {
+        let value: String = value.nfc().collect();
+        let value = match self.collapse_whitespace {
+            true => value.split_whitespace().collect::<Vec<_>>().join(" "),
+            false => value,
+        };
+        match self.case {
+            NameCase::Preserve => value,
+            NameCase::Title => title_case(&value),
+            NameCase::Lower => value.to_lowercase(),
+            NameCase::Upper => value.to_uppercase(),
+        }
+    }
+
+    /// Compose the name values out of the (optional) given and family names.
+    /// Returns `None` if there is no name, or if a name part is present but
+    /// blank.
+    pub(crate) fn compose(
+        &self,
+        given: Option<&str>,
+        family: Option<&str>,
+    ) -> Option<ComposedName> {
+        let given = given.map(|n| self.normalize(n));
+        let family = family.map(|n| self.normalize(n));
+        match (given, family) {
+            (Some(given_name), Some(family_name))
+                if !given_name.trim().is_empty() && !family_name.trim().is_empty() =>
+            {
+                let name = fill_template(&self.template, &given_name, &family_name);
+                Some(ComposedName {
+                    given_name,
+                    family_name,
+                    name,
+                })
+            }
+            (Some(name), None) | (None, Some(name)) if !name.trim().is_empty() => {
+                Some(match self.mononym {
+                    Mononym::Given => ComposedName {
+                        given_name: name.clone(),
+                        family_name: String::new(),
+                        name,
+                    },
+                    Mononym::Family => ComposedName {
+                        given_name: String::new(),
+                        family_name: name.clone(),
+                        name,
+                    },
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Substitute the `{given}` and `{family}` placeholders of a template in a
+/// single pass, so that placeholders within the names are left as they are
+fn fill_template(template: &str, given: &str, family: &str) -> String {
+    let mut name = String::with_capacity(template.len() + given.len() + family.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        if let Some(tail) = tail.strip_prefix("{given}") {
+            name.push_str(given);
+            rest = tail;
+        } else if let Some(tail) = tail.strip_prefix("{family}") {
+            name.push_str(family);
+            rest = tail;
+        } else {
+            name.push('{');
+            rest = &tail[1..];
+        }
+    }
+    name.push_str(rest);
+    name
+}
+
+/// Uppercase the first letter of each word and lowercase the rest, treating
+/// hyphens and apostrophes as word boundaries (e.g. "o'brien-smith" -> "O'Brien-Smith")
+fn title_case(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut word_start = true;
+    for c in value.chars() {
+        if word_start {
+            result.extend(c.to_uppercase());
+        } else {
+            result.extend(c.to_lowercase());
+        }
+        word_start = c.is_whitespace() || matches!(c, '-' | '\'' | '’' | '(');
+    }
+    result
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct NameExclusions {
+    /// Regular expression for names to leave out. Can be repeated.
+    #[arg(long = "exclude-name", value_name = "REGEX")]
+    pub patterns: Vec<String>,
+
+    /// Path to a file with regular expressions (one per line) for names to leave
+    /// out. Empty lines and lines starting with `#` are ignored.
+    #[arg(long = "exclude-names-file")]
+    pub file: Option<PathBuf>,
+}
+
+/// Filter on display names, built from `--filter-name` and the name exclusions
+#[derive(Debug, Default, Clone)]
+pub(crate) struct NameFilter {
+    include: Option<Regex>,
+    exclude: Option<RegexSet>,
+}
+
+impl NameFilter {
+    /// Compile the name patterns, failing on any invalid regular expression
+    pub(crate) fn new(filter_name: &Option<String>, exclusions: &NameExclusions) -> Result<Self> {
+        let include = filter_name
+            .as_deref()
+            .map(|re| Regex::new(re).with_context(|| format!("Invalid name filter {:?}", re)))
+            .transpose()?;
+
+        let mut patterns = exclusions.patterns.clone();
+        if let Some(path) = &exclusions.file {
+            let content = fs::read_to_string(path)
+                .input_context(|| format!("Error reading file {}", path.display()))?;
+            patterns.extend(
+                content
+                    .lines()
+                    .map(str::trim)
+                    .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                    .map(String::from),
+            );
+        }
+        // Compile individually first, to point at the invalid pattern
+        for pattern in &patterns {
+            Regex::new(pattern)
+                .with_context(|| format!("Invalid name exclusion pattern {:?}", pattern))?;
+        }
+        let exclude = (!patterns.is_empty())
+            .then(|| RegexSet::new(&patterns))
+            .transpose()?;
+        Ok(NameFilter { include, exclude })
+    }
+
+    pub(crate) fn is_match(&self, name: &str) -> bool {
+        self.include.as_ref().is_none_or(|re| re.is_match(name))
+            && !self.exclude.as_ref().is_some_and(|re| re.is_match(name))
+    }
+}
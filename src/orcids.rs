@@ -0,0 +1,95 @@
+//! Selection of records by ORCiD iD.
+
+use std::{collections::HashSet, fs, path::Path, path::PathBuf};
+
+use anyhow::{bail, Result};
+use clap::Args;
+
+use crate::error::ErrorContext;
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct OrcidSelection {
+    /// Path to a file with the ORCiD iDs (one per line) to keep, leaving out all
+    /// other records. Empty lines and lines starting with `#` are ignored.
+    #[arg(long = "only-orcids")]
+    pub only: Option<PathBuf>,
+
+    /// Path to a file with the ORCiD iDs (one per line) to leave out
+    #[arg(long = "exclude-orcids")]
+    pub exclude: Option<PathBuf>,
+}
+
+/// Filter on ORCiD iDs, built from an `OrcidSelection`
+#[derive(Debug, Default)]
+pub(crate) struct OrcidFilter {
+    only: Option<HashSet<String>>,
+    exclude: HashSet<String>,
+}
+
+impl OrcidFilter {
+    pub(crate) fn new(selection: &OrcidSelection) -> Result<Self> {
+        Ok(OrcidFilter {
+            only: selection.only.as_deref().map(read_orcids).transpose()?,
+            exclude: selection
+                .exclude
+                .as_deref()
+                .map(read_orcids)
+                .transpose()?
+                .unwrap_or_default(),
+        })
+    }
+
+    pub(crate) fn is_match(&self, orcid: &str) -> bool {
+        self.only.as_ref().is_none_or(|only| only.contains(orcid)) && !self.exclude.contains(orcid)
+    }
+
+    /// Whether the record stored at `path` in the ORCiD data file, i.e.
+    /// `<dir>/<checksum digits>/<ORCiD iD>.xml`, is selected
+    pub(crate) fn is_path_match(&self, path: &Path) -> bool {
+        if self.only.is_none() && self.exclude.is_empty() {
+            return true;
+        }
+        path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .is_some_and(|orcid| self.is_match(orcid))
+    }
+}
+
+/// Read ORCiD iDs from a file, accepting both bare iDs and `https://orcid.org/` URIs
+fn read_orcids(path: &Path) -> Result<HashSet<String>> {
+    let content = fs::read_to_string(path)
+        .input_context(|| format!("Error reading file {}", path.display()))?;
+    let mut orcids = HashSet::new();
+    for (idx, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let orcid = line
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or(line);
+        if !is_orcid(orcid) {
+            bail!(
+                "Invalid ORCiD iD {:?} on line {} of {}",
+                line,
+                idx + 1,
+                path.display()
+            );
+        }
+        orcids.insert(orcid.to_string());
+    }
+    Ok(orcids)
+}
+
+/// Whether `value` is a (syntactically valid) ORCiD iD, e.g. "0000-0002-5082-6404"
+pub(crate) fn is_orcid(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    bytes.len() == 19
+        && bytes.iter().enumerate().all(|(idx, b)| match idx {
+            4 | 9 | 14 => *b == b'-',
+            18 => b.is_ascii_digit() || *b == b'X',
+            _ => b.is_ascii_digit(),
+        })
+}
@@ -0,0 +1,490 @@
+//! Python bindings, built with the `python` feature into the
+//! `orcid_data_toolkit` Python package by maturin (see `pyproject.toml`).
+//!
+//! ```python
+//! import orcid_data_toolkit as odt
+//!
+//! mappings = odt.OrgMappings.from_csv("org-mappings.csv")
+//! converter = odt.NameConverter(orgs_mapping=mappings, other_names=True)
+//! odt.convert("ORCID_2024_10_summaries.tar.gz", "names.csv", converter=converter)
+//! for name in odt.NameReader("ORCID_2024_10_summaries.tar.gz", converter=converter):
+//!     print(name["name"])
+//! ```
+
+use std::{
+    ffi::{CString, OsStr},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use anyhow::anyhow;
+use chrono::{DateTime, FixedOffset};
+use clap::ValueEnum;
+use pyo3::{
+    exceptions::{PyOSError, PyRuntimeError, PyRuntimeWarning, PyValueError},
+    prelude::*,
+    types::{PyBool, PyDict, PyList, PyString},
+};
+
+use crate::error::ErrorContext;
+use crate::{
+    affiliations::{parse_country, parse_ror_id},
+    convert_tgz_with, convert_xml_with,
+    names::parse_template,
+    read_org_ids,
+    record::Record,
+    scripts::parse_script,
+    shards::MAX_SHARDS,
+    AffiliationFilter, CheckpointOptions, ConvertFormat, ConvertOptions, ErrorKind,
+    ExtractedIdentifier, NameChecks, NameConverter, NameExclusions, NameJson, NamePolicy,
+    OrcidSelection, OrgMap, Output, RecordReader, ScriptOptions, ShardOptions, SortOptions,
+    DEFAULT_NAME_FILTER_REGEX, DEFAULT_NAME_TEMPLATE,
+};
+
+/// Convert an error to the Python exception of its kind
+fn to_py_err(err: anyhow::Error) -> PyErr {
+    let message = format!("{:#}", err);
+    match ErrorKind::of(&err) {
+        Some(ErrorKind::Input | ErrorKind::Output) => PyOSError::new_err(message),
+        Some(ErrorKind::Parse) => PyValueError::new_err(message),
+        None => PyRuntimeError::new_err(message),
+    }
+}
+
+/// Convert a name to a dict, or `None` for records that are skipped by design
+/// (see `NameConverter::convert`)
+fn converted_to_python<'py>(
+    py: Python<'py>,
+    converted: anyhow::Result<Option<NameJson>>,
+) -> PyResult<Option<Bound<'py, PyAny>>> {
+    match converted.map_err(to_py_err)? {
+        Some(name) => name_to_python(py, &name).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Parse a keyword argument like the value of its command line option
+fn parse_arg<T>(name: &str, value: &str, parse: impl Fn(&str) -> Result<T, String>) -> PyResult<T> {
+    parse(value)
+        .map_err(|err| PyValueError::new_err(format!("Invalid {} {:?}: {}", name, value, err)))
+}
+
+/// Parse each value of a keyword argument (see `parse_arg`)
+fn parse_args<T>(
+    name: &str,
+    values: &[String],
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> PyResult<Vec<T>> {
+    values
+        .iter()
+        .map(|value| parse_arg(name, value, &parse))
+        .collect()
+}
+
+/// Parse a keyword argument given as the name of a command line value
+fn parse_enum<T: ValueEnum>(name: &str, value: &str) -> PyResult<T> {
+    parse_arg(name, value, |value| T::from_str(value, true))
+}
+
+/// Convert a JSON value to the equivalent Python object
+fn to_python<'py>(py: Python<'py>, value: &serde_json::Value) -> PyResult<Bound<'py, PyAny>> {
+    use serde_json::Value;
+    Ok(match value {
+        Value::Null => py.None().into_bound(py),
+        Value::Bool(b) => PyBool::new(py, *b).to_owned().into_any(),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => i.into_pyobject(py)?.into_any(),
+            None => n.as_f64().unwrap_or(f64::NAN).into_pyobject(py)?.into_any(),
+        },
+        Value::String(s) => PyString::new(py, s).into_any(),
+        Value::Array(values) => {
+            let list = PyList::empty(py);
+            for value in values {
+                list.append(to_python(py, value)?)?;
+            }
+            list.into_any()
+        }
+        Value::Object(map) => {
+            let dict = PyDict::new(py);
+            for (key, value) in map {
+                dict.set_item(key, to_python(py, value)?)?;
+            }
+            dict.into_any()
+        }
+    })
+}
+
+/// Convert a name to a dict, shaped like the output of `convert --format json`
+fn name_to_python<'py>(py: Python<'py>, name: &NameJson) -> PyResult<Bound<'py, PyAny>> {
+    let value =
+        serde_json::to_value(name).map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+    to_python(py, &value)
+}
+
+/// Mappings of organization identifiers (e.g. GRID or Ringgold IDs) to ROR IDs
+#[pyclass(module = "orcid_data_toolkit")]
+#[derive(Clone, Default)]
+struct OrgMappings {
+    map: OrgMap,
+}
+
+#[pymethods]
+impl OrgMappings {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the mappings of a CSV file of scheme, identifier and ROR ID, as
+    /// passed to `--orgs-mapping`
+    #[staticmethod]
+    fn from_csv(path: PathBuf) -> PyResult<Self> {
+        let map = read_org_ids(&Some(path)).map_err(to_py_err)?;
+        Ok(OrgMappings { map })
+    }
+
+    /// Map an organization identifier to a ROR ID
+    fn add(&mut self, scheme: String, identifier: String, ror_id: String) {
+        self.map
+            .insert(ExtractedIdentifier { scheme, identifier }, ror_id);
+    }
+
+    /// ROR ID an organization identifier is mapped to, if any
+    fn get(&self, scheme: String, identifier: String) -> Option<String> {
+        self.map
+            .get(&ExtractedIdentifier { scheme, identifier })
+            .cloned()
+    }
+
+    /// Write the mappings to a CSV file, sorted by scheme and identifier
+    fn to_csv(&self, path: PathBuf) -> PyResult<()> {
+        let write = || {
+            let mut mappings: Vec<_> = self.map.iter().collect();
+            mappings.sort();
+            let mut output = Output::create(&path)?;
+            let mut writer = csv::Writer::from_writer(&mut output);
+            for (id, ror_id) in mappings {
+                writer
+                    .serialize((&id.scheme, &id.identifier, ror_id))
+                    .output_context(|| format!("Error writing file {}", path.display()))?;
+            }
+            writer
+                .flush()
+                .output_context(|| format!("Error writing file {}", path.display()))?;
+            drop(writer);
+            output.commit()
+        };
+        write().map_err(to_py_err)
+    }
+
+    fn __len__(&self) -> usize {
+        self.map.len()
+    }
+}
+
+/// Organization mappings, given as `OrgMappings` or the path to a CSV file
+#[derive(FromPyObject)]
+enum OrgsMapping {
+    Mappings(OrgMappings),
+    Path(PathBuf),
+}
+
+/// Converts records to InvenioRDM names, with the options of `convert`
+#[pyclass(name = "NameConverter", module = "orcid_data_toolkit", frozen)]
+struct PyNameConverter {
+    converter: Arc<NameConverter>,
+}
+
+#[pymethods]
+impl PyNameConverter {
+    /// The keyword arguments are the options of `convert` that select and
+    /// shape the names, e.g. `other_names=True` for `--other-names` or
+    /// `person_ids=["ISNI"]` for `--person-id ISNI`. `filter_name=None` keeps
+    /// all names.
+    #[new]
+    #[pyo3(signature = (
+        *,
+        orgs_mapping = None,
+        filter_name = Some(DEFAULT_NAME_FILTER_REGEX.to_string()),
+        person_ids = Vec::new(),
+        use_credit_name = false,
+        other_names = false,
+        name_template = DEFAULT_NAME_TEMPLATE,
+        mononym = "family",
+        name_case = "preserve",
+        collapse_whitespace = false,
+        name_checks = "off",
+        skip_name_checks = Vec::new(),
+        exclude_names = Vec::new(),
+        exclude_names_file = None,
+        allow_scripts = Vec::new(),
+        annotate_scripts = false,
+        transliterate = false,
+        only_orcids = None,
+        exclude_orcids = None,
+        affiliation_rors = Vec::new(),
+        affiliation_countries = Vec::new(),
+        any_affiliation = false,
+        no_affiliation = false,
+        include_past_employments = false,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        orgs_mapping: Option<OrgsMapping>,
+        filter_name: Option<String>,
+        person_ids: Vec<String>,
+        use_credit_name: bool,
+        other_names: bool,
+        name_template: &str,
+        mononym: &str,
+        name_case: &str,
+        collapse_whitespace: bool,
+        name_checks: &str,
+        skip_name_checks: Vec<String>,
+        exclude_names: Vec<String>,
+        exclude_names_file: Option<PathBuf>,
+        allow_scripts: Vec<String>,
+        annotate_scripts: bool,
+        transliterate: bool,
+        only_orcids: Option<PathBuf>,
+        exclude_orcids: Option<PathBuf>,
+        affiliation_rors: Vec<String>,
+        affiliation_countries: Vec<String>,
+        any_affiliation: bool,
+        no_affiliation: bool,
+        include_past_employments: bool,
+    ) -> PyResult<Self> {
+        if any_affiliation && no_affiliation {
+            return Err(PyValueError::new_err(
+                "any_affiliation and no_affiliation can't be combined",
+            ));
+        }
+        let options = ConvertOptions {
+            person_ids: parse_args("person_ids", &person_ids, str::parse)?,
+            use_credit_name,
+            other_names,
+            name_policy: NamePolicy {
+                template: parse_arg("name_template", name_template, parse_template)?,
+                mononym: parse_enum("mononym", mononym)?,
+                case: parse_enum("name_case", name_case)?,
+                collapse_whitespace,
+            },
+            name_checks: NameChecks {
+                mode: parse_enum("name_checks", name_checks)?,
+                skipped: parse_args("skip_name_checks", &skip_name_checks, |check| {
+                    ValueEnum::from_str(check, true)
+                })?,
+                review_file: None,
+            },
+            exclude_names: NameExclusions {
+                patterns: exclude_names,
+                file: exclude_names_file,
+            },
+            scripts: ScriptOptions {
+                allowed: parse_args("allow_scripts", &allow_scripts, parse_script)?,
+                annotate: annotate_scripts,
+                transliterate,
+            },
+            orcids: OrcidSelection {
+                only: only_orcids,
+                exclude: exclude_orcids,
+            },
+            affiliations: AffiliationFilter {
+                ror_ids: parse_args("affiliation_rors", &affiliation_rors, parse_ror_id)?,
+                countries: parse_args(
+                    "affiliation_countries",
+                    &affiliation_countries,
+                    parse_country,
+                )?,
+                any_affiliation,
+                no_affiliation,
+                include_past: include_past_employments,
+            },
+            ..ConvertOptions::default()
+        };
+        let mut converter =
+            NameConverter::build(&options, &filter_name, &None).map_err(to_py_err)?;
+        match orgs_mapping {
+            Some(OrgsMapping::Mappings(mappings)) => converter.org_map = mappings.map,
+            Some(OrgsMapping::Path(path)) => {
+                converter = converter.with_org_mappings(&path).map_err(to_py_err)?
+            }
+            None => {}
+        }
+        Ok(PyNameConverter {
+            converter: Arc::new(converter),
+        })
+    }
+
+    /// Convert the XML of a record to a name (a dict shaped like the output
+    /// of `convert --format json`). Returns `None` for inactive records,
+    /// records without a name, and names that are filtered out or rejected
+    /// by the name checks.
+    fn convert<'py>(&self, py: Python<'py>, xml: &str) -> PyResult<Option<Bound<'py, PyAny>>> {
+        let record =
+            Record::from_xml(xml).map_err(|err| PyValueError::new_err(format!("{:#}", err)))?;
+        converted_to_python(py, self.converter.convert(&record))
+    }
+}
+
+fn converter_or_default(
+    converter: Option<PyRef<'_, PyNameConverter>>,
+) -> PyResult<Arc<NameConverter>> {
+    match converter {
+        Some(converter) => Ok(converter.converter.clone()),
+        None => {
+            let filter_name = Some(DEFAULT_NAME_FILTER_REGEX.to_string());
+            let converter = NameConverter::build(&ConvertOptions::default(), &filter_name, &None)
+                .map_err(to_py_err)?;
+            Ok(Arc::new(converter))
+        }
+    }
+}
+
+/// Iterator over the names of a `.tar.gz` file, a directory or an XML file,
+/// as dicts shaped like the output of `convert --format json`.
+///
+/// Records that fail to parse are reported as a `RuntimeWarning` and skipped,
+/// like records that are inactive, filtered out or without a name.
+#[pyclass(module = "orcid_data_toolkit")]
+struct NameReader {
+    reader: RecordReader,
+    converter: Arc<NameConverter>,
+}
+
+#[pymethods]
+impl NameReader {
+    #[new]
+    #[pyo3(signature = (input_file, *, converter = None))]
+    fn new(input_file: PathBuf, converter: Option<PyRef<'_, PyNameConverter>>) -> PyResult<Self> {
+        Ok(NameReader {
+            reader: RecordReader::open(input_file).map_err(to_py_err)?,
+            converter: converter_or_default(converter)?,
+        })
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyAny>>> {
+        loop {
+            // Reading waits for decompression, which doesn't need the GIL
+            let reader = &mut self.reader;
+            let Some(result) = py.detach(|| reader.next()) else {
+                return Ok(None);
+            };
+            let record = match result {
+                Ok(record) => record,
+                Err(err) if ErrorKind::of(&err) == Some(ErrorKind::Parse) => {
+                    let message = CString::new(format!("{:#}", err).replace('\0', " "))?;
+                    let category = py.get_type::<PyRuntimeWarning>();
+                    PyErr::warn(py, &category, &message, 1)?;
+                    continue;
+                }
+                Err(err) => return Err(to_py_err(err)),
+            };
+            if let Some(name) = converted_to_python(py, self.converter.convert(&record))? {
+                return Ok(Some(name));
+            }
+        }
+    }
+}
+
+/// Convert a `.tar.gz` or XML file, like the `convert` command. The keyword
+/// arguments after `converter` are the options of `convert` about the output,
+/// e.g. `sort_by="orcid"` for `--sort-by orcid`.
+#[pyfunction]
+#[pyo3(signature = (
+    input_file,
+    output_file,
+    format = "invenio-rdm-names",
+    *,
+    converter = None,
+    index_file = None,
+    review_file = None,
+    redirects_file = None,
+    timestamp = None,
+    es_index = None,
+    sort_by = None,
+    sort_memory = 256,
+    tmp_dir = None,
+    shard_size = None,
+    shards = None,
+    checkpoint_interval = None,
+    resume = false,
+))]
+#[allow(clippy::too_many_arguments)]
+fn convert(
+    py: Python<'_>,
+    input_file: PathBuf,
+    output_file: PathBuf,
+    format: &str,
+    converter: Option<PyRef<'_, PyNameConverter>>,
+    index_file: Option<PathBuf>,
+    review_file: Option<PathBuf>,
+    redirects_file: Option<PathBuf>,
+    timestamp: Option<&str>,
+    es_index: Option<String>,
+    sort_by: Option<&str>,
+    sort_memory: usize,
+    tmp_dir: Option<PathBuf>,
+    shard_size: Option<u64>,
+    shards: Option<u64>,
+    checkpoint_interval: Option<u64>,
+    resume: bool,
+) -> PyResult<()> {
+    let format = parse_enum::<ConvertFormat>("format", format)?;
+    if shard_size == Some(0) || shards.is_some_and(|n| !(1..=MAX_SHARDS).contains(&n)) {
+        return Err(PyValueError::new_err(format!(
+            "shard_size must be at least 1, and shards in 1..={}",
+            MAX_SHARDS
+        )));
+    }
+    if shard_size.is_some() && shards.is_some() {
+        return Err(PyValueError::new_err(
+            "shard_size and shards can't be combined",
+        ));
+    }
+    let mut converter = NameConverter::clone(&*converter_or_default(converter)?);
+    let options = &mut converter.options;
+    options.name_checks.review_file = review_file;
+    options.redirects_file = redirects_file;
+    options.timestamp = timestamp
+        .map(|timestamp| {
+            parse_arg("timestamp", timestamp, |timestamp| {
+                timestamp
+                    .parse::<DateTime<FixedOffset>>()
+                    .map_err(|err| err.to_string())
+            })
+        })
+        .transpose()?;
+    options.es_index = es_index;
+    options.sort = SortOptions {
+        sort_by: sort_by.map(|key| parse_enum("sort_by", key)).transpose()?,
+        sort_memory,
+        tmp_dir,
+    };
+    options.shards = ShardOptions { shard_size, shards };
+    options.checkpoints = CheckpointOptions {
+        interval: checkpoint_interval,
+        resume,
+    };
+    py.detach(|| match input_file.extension().and_then(OsStr::to_str) {
+        Some("xml") => convert_xml_with(&converter, &input_file, &output_file, &format),
+        Some("gz") => convert_tgz_with(&converter, &input_file, &output_file, &format, &index_file),
+        _ => Err(anyhow!("Unsupported file extension")),
+    })
+    .map_err(to_py_err)
+}
+
+#[pymodule]
+#[pyo3(name = "orcid_data_toolkit")]
+fn python_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<OrgMappings>()?;
+    m.add_class::<PyNameConverter>()?;
+    m.add_class::<NameReader>()?;
+    m.add_function(wrap_pyfunction!(convert, m)?)?;
+    m.add("DEFAULT_NAME_FILTER_REGEX", DEFAULT_NAME_FILTER_REGEX)?;
+    Ok(())
+}
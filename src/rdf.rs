@@ -0,0 +1,172 @@
+//! Names as schema.org RDF, as N-Triples or JSON-LD.
+//!
+//! Each ORCiD iD is a `schema:Person` identified by its ORCiD URI, with its
+//! `givenName`, `familyName` (unless empty), `name` and `alternateName`s. Its
+//! identifiers are `schema:PropertyValue`s of their scheme and value, and its
+//! affiliations either ROR URIs (when resolved) or `schema:Organization`s with
+//! a name. JSON-LD documents use an inline `@context` whose `@vocab` is the
+//! same `https://schema.org/` namespace as the N-Triples.
+//!
+//! Both formats are written per record: N-Triples lines, and JSON-LD documents
+//! as JSON Lines. Blank nodes are labeled by ORCiD iD, so that the triples of
+//! all records can be loaded together.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use crate::NameJson;
+
+const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+const SCHEMA: &str = "https://schema.org/";
+
+/// An RDF term of an N-Triples statement
+enum Term<'a> {
+    Iri(&'a str),
+    BlankNode(&'a str),
+    Literal(&'a str),
+}
+
+impl std::fmt::Display for Term<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Term::Iri(iri) => write!(f, "<{}>", iri),
+            Term::BlankNode(label) => write!(f, "_:{}", label),
+            Term::Literal(value) => {
+                f.write_str("\"")?;
+                for c in value.chars() {
+                    match c {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\r' => f.write_str("\\r")?,
+                        '\t' => f.write_str("\\t")?,
+                        c if c.is_control() => write!(f, "\\u{:04X}", c as u32)?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                f.write_str("\"")
+            }
+        }
+    }
+}
+
+fn person_iri(orcid: &str) -> String {
+    format!("https://orcid.org/{}", orcid)
+}
+
+/// URI of a ROR ID, which may be given as a bare ID (e.g. "01ggx4157") or as
+/// a ROR URI. Returns `None` for IDs that can't be part of an IRI reference
+/// (e.g. with spaces or `>`), whose affiliations are then encoded by name.
+fn ror_iri(ror_id: &str) -> Option<String> {
+    let id = ror_id
+        .strip_prefix("https://ror.org/")
+        .or_else(|| ror_id.strip_prefix("http://ror.org/"))
+        .unwrap_or(ror_id);
+    // Characters that IRIREF of N-Triples excludes
+    let invalid = |c: char| c <= ' ' || "<>\"{}|^`\\".contains(c);
+    if id.is_empty() || id.contains(invalid) {
+        return None;
+    }
+    Some(format!("https://ror.org/{}", id))
+}
+
+/// Encode a name as N-Triples
+pub(crate) fn encode_ntriples(orcid: &str, name: &NameJson) -> Result<String> {
+    let mut triples = String::new();
+    let mut add = |subject: &Term, predicate: &str, object: &Term| {
+        triples += &format!("{} <{}> {} .\n", subject, predicate, object);
+    };
+    let schema = |term: &str| format!("{}{}", SCHEMA, term);
+
+    let iri = person_iri(orcid);
+    let person = Term::Iri(&iri);
+    add(&person, RDF_TYPE, &Term::Iri(&schema("Person")));
+    if !name.given_name.is_empty() {
+        add(
+            &person,
+            &schema("givenName"),
+            &Term::Literal(&name.given_name),
+        );
+    }
+    if !name.family_name.is_empty() {
+        add(
+            &person,
+            &schema("familyName"),
+            &Term::Literal(&name.family_name),
+        );
+    }
+    add(&person, &schema("name"), &Term::Literal(&name.name));
+    for alternative_name in name.alternative_names.iter().flatten() {
+        add(
+            &person,
+            &schema("alternateName"),
+            &Term::Literal(alternative_name),
+        );
+    }
+    for (i, identifier) in name.identifiers.iter().enumerate() {
+        let label = format!("orcid-{}-identifier-{}", orcid, i + 1);
+        let node = Term::BlankNode(&label);
+        add(&person, &schema("identifier"), &node);
+        add(&node, RDF_TYPE, &Term::Iri(&schema("PropertyValue")));
+        add(
+            &node,
+            &schema("propertyID"),
+            &Term::Literal(&identifier.scheme),
+        );
+        add(
+            &node,
+            &schema("value"),
+            &Term::Literal(&identifier.identifier),
+        );
+    }
+    for (i, affiliation) in name.affiliations.iter().flatten().enumerate() {
+        match affiliation.id.as_deref().and_then(ror_iri) {
+            Some(iri) => add(&person, &schema("affiliation"), &Term::Iri(&iri)),
+            None => {
+                let label = format!("orcid-{}-affiliation-{}", orcid, i + 1);
+                let node = Term::BlankNode(&label);
+                add(&person, &schema("affiliation"), &node);
+                add(&node, RDF_TYPE, &Term::Iri(&schema("Organization")));
+                add(&node, &schema("name"), &Term::Literal(&affiliation.name));
+            }
+        }
+    }
+    Ok(triples)
+}
+
+/// Encode a name as a (single line) JSON-LD document
+pub(crate) fn encode_jsonld(orcid: &str, name: &NameJson) -> Result<String> {
+    let mut person = json!({
+        "@context": { "@vocab": SCHEMA },
+        "@id": person_iri(orcid),
+        "@type": "Person",
+        "name": name.name,
+        "identifier": name.identifiers.iter().map(|identifier| json!({
+            "@type": "PropertyValue",
+            "propertyID": identifier.scheme,
+            "value": identifier.identifier,
+        })).collect::<Vec<_>>(),
+    });
+    if !name.given_name.is_empty() {
+        person["givenName"] = Value::from(name.given_name.as_str());
+    }
+    if !name.family_name.is_empty() {
+        person["familyName"] = Value::from(name.family_name.as_str());
+    }
+    if let Some(alternative_names) = &name.alternative_names {
+        person["alternateName"] = json!(alternative_names);
+    }
+    if let Some(affiliations) = &name.affiliations {
+        let affiliations: Vec<_> = affiliations
+            .iter()
+            .map(
+                |affiliation| match affiliation.id.as_deref().and_then(ror_iri) {
+                    Some(iri) => json!({ "@id": iri }),
+                    None => json!({ "@type": "Organization", "name": affiliation.name }),
+                },
+            )
+            .collect();
+        person["affiliation"] = Value::from(affiliations);
+    }
+    Ok(serde_json::to_string(&person)? + "\n")
+}
@@ -0,0 +1,151 @@
+//! Reading records from the ORCiD Public Data Summaries file, a directory of
+//! (e.g. extracted) record XML files or a single XML file.
+
+use std::{
+    ffi::OsStr,
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use anyhow::{bail, Result};
+use crossbeam_channel::{bounded, Receiver, Sender};
+use flate2::read::GzDecoder;
+use tar::Archive;
+
+use crate::error::ErrorContext;
+use crate::record::Record;
+
+/// The XML of a record, with the path it was read from
+type RecordXml = (String, String);
+
+enum Source {
+    /// Records read from a `.tar.gz` file by a background thread
+    Archive(Receiver<Result<RecordXml>>),
+    Files(std::vec::IntoIter<PathBuf>),
+}
+
+/// Iterator over the records of a `.tar.gz` file, a directory or an XML file.
+///
+/// All records are returned, including deprecated, locked and deactivated
+/// ones (see [`Record::status`]). Records that can't be read or parsed are
+/// returned as errors, after which reading continues with the next record,
+/// except for errors reading the archive itself.
+///
+/// ```
+/// use orcid_data_toolkit::RecordReader;
+///
+/// for record in RecordReader::open("tests/data/alex.xml")? {
+///     let record = record?;
+///     assert_eq!(record.identifier.path, "0000-0002-5082-6404");
+/// }
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct RecordReader {
+    source: Source,
+}
+
+impl RecordReader {
+    /// Open a `.tar.gz` file, a directory (read recursively, in path order) or
+    /// an XML file
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = if path.is_dir() {
+            let mut files = vec![];
+            find_xml_files(path, &mut files)?;
+            files.sort();
+            Source::Files(files.into_iter())
+        } else {
+            match path.extension().and_then(OsStr::to_str) {
+                Some("xml") => Source::Files(vec![path.to_path_buf()].into_iter()),
+                Some("gz") => Source::Archive(read_archive(path)?),
+                _ => bail!("Unsupported file extension of {}", path.display()),
+            }
+        };
+        Ok(RecordReader { source })
+    }
+}
+
+impl Iterator for RecordReader {
+    type Item = Result<Record<'static>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = match &mut self.source {
+            Source::Archive(rx) => rx.recv().ok()?,
+            Source::Files(files) => {
+                let path = files.next()?;
+                fs::read_to_string(&path)
+                    .input_context(|| format!("Error reading file {}", path.display()))
+                    .map(|xml| (path.display().to_string(), xml))
+            }
+        };
+        Some(result.and_then(|(path, mut xml)| {
+            Record::from_xml_mut(&mut xml)
+                .map(Record::into_owned)
+                .parse_context(|| format!("Error parsing {}", path))
+        }))
+    }
+}
+
+fn find_xml_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries =
+        fs::read_dir(dir).input_context(|| format!("Error reading directory {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_xml_files(&path, files)?;
+        } else if path.extension().and_then(OsStr::to_str) == Some("xml") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Read the XML entries of a `.tar.gz` file in a background thread, so that
+/// decompression overlaps with parsing
+fn read_archive(path: &Path) -> Result<Receiver<Result<RecordXml>>> {
+    let file =
+        File::open(path).input_context(|| format!("Error opening file {}", path.display()))?;
+    let archive_path = path.to_path_buf();
+    let (tx, rx) = bounded(64);
+    thread::spawn(move || {
+        if let Err(err) = send_records(file, &archive_path, &tx) {
+            let _ = tx.send(Err(err));
+        }
+    });
+    Ok(rx)
+}
+
+fn send_records(file: File, archive_path: &Path, tx: &Sender<Result<RecordXml>>) -> Result<()> {
+    let mut archive = Archive::new(GzDecoder::new(file));
+    let entries = archive
+        .entries()
+        .input_context(|| format!("Error reading file {}", archive_path.display()))?;
+    for entry in entries {
+        let mut entry =
+            entry.input_context(|| format!("Error reading file {}", archive_path.display()))?;
+        let path = entry
+            .path()
+            .input_context(|| format!("Error reading file {}", archive_path.display()))?
+            .display()
+            .to_string();
+        if !path.ends_with(".xml") {
+            continue;
+        }
+        let mut xml = String::new();
+        let result = match entry.read_to_string(&mut xml) {
+            Ok(_) => Ok((path, xml)),
+            // Only this record is affected
+            Err(err) if err.kind() == std::io::ErrorKind::InvalidData => {
+                Err(err).parse_context(|| format!("Error reading {}", path))
+            }
+            Err(err) => return Err(err).input_context(|| format!("Error reading {}", path)),
+        };
+        // Stop when the reader was dropped
+        if tx.send(result).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,775 @@
+//! Typed model of the records of the ORCiD Public Data Summaries file.
+//!
+//! Only the parts of records that are used by the toolkit (i.e. the person
+//! details and employments) are modelled. Records borrow their strings from the
+//! XML they were parsed from, only allocating for values with escaped
+//! characters. Use [`Record::into_owned`] to detach a record from its XML.
+
+use std::{borrow::Cow, ops::Range, sync::LazyLock};
+
+use anyhow::{bail, Result};
+use memchr::{memchr, memmem};
+use quick_xml::de::Deserializer;
+use regex::Regex;
+use serde::Deserialize;
+
+/// Deserialize an optional string, borrowing it from the input when possible
+/// (`#[serde(borrow)]` only borrows non-optional `Cow` fields)
+fn borrow_option<'de: 'a, 'a, D>(deserializer: D) -> Result<Option<Cow<'a, str>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Borrowed<'a>(#[serde(borrow)] Cow<'a, str>);
+    Ok(Option::<Borrowed>::deserialize(deserializer)?.map(|b| b.0))
+}
+
+/// An ORCiD iD, e.g. `path` "0000-0002-5082-6404"
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Identifier<'a> {
+    #[serde(rename = "uri", borrow)]
+    pub uri: Cow<'a, str>,
+    #[serde(rename = "path", borrow)]
+    pub path: Cow<'a, str>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct PersonName<'a> {
+    #[serde(rename = "@visibility")]
+    #[serde(borrow, default, deserialize_with = "borrow_option")]
+    pub visibility: Option<Cow<'a, str>>,
+    #[serde(rename = "given-names")]
+    #[serde(borrow, default, deserialize_with = "borrow_option")]
+    pub given_names: Option<Cow<'a, str>>,
+    #[serde(rename = "family-name")]
+    #[serde(borrow, default, deserialize_with = "borrow_option")]
+    pub family_name: Option<Cow<'a, str>>,
+    #[serde(rename = "credit-name")]
+    #[serde(borrow, default, deserialize_with = "borrow_option")]
+    pub credit_name: Option<Cow<'a, str>>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct OtherName<'a> {
+    #[serde(rename = "@visibility")]
+    #[serde(borrow, default, deserialize_with = "borrow_option")]
+    pub visibility: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub content: Cow<'a, str>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct OtherNames<'a> {
+    #[serde(rename = "other-name", borrow)]
+    pub names: Option<Vec<OtherName<'a>>>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct PersonExternalIdentifier<'a> {
+    #[serde(rename = "@visibility")]
+    #[serde(borrow, default, deserialize_with = "borrow_option")]
+    pub visibility: Option<Cow<'a, str>>,
+    #[serde(rename = "external-id-type", borrow)]
+    pub id_type: Cow<'a, str>,
+    #[serde(rename = "external-id-value", borrow)]
+    pub value: Cow<'a, str>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct PersonExternalIdentifiers<'a> {
+    #[serde(rename = "external-identifier", borrow)]
+    pub identifiers: Option<Vec<PersonExternalIdentifier<'a>>>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct ResearcherUrl<'a> {
+    #[serde(rename = "@visibility")]
+    #[serde(borrow, default, deserialize_with = "borrow_option")]
+    pub visibility: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub url: Cow<'a, str>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct ResearcherUrls<'a> {
+    #[serde(rename = "researcher-url", borrow)]
+    pub urls: Option<Vec<ResearcherUrl<'a>>>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Keyword<'a> {
+    #[serde(rename = "@visibility")]
+    #[serde(borrow, default, deserialize_with = "borrow_option")]
+    pub visibility: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub content: Cow<'a, str>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Keywords<'a> {
+    #[serde(borrow)]
+    pub keyword: Option<Vec<Keyword<'a>>>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Email<'a> {
+    #[serde(rename = "@visibility")]
+    #[serde(borrow, default, deserialize_with = "borrow_option")]
+    pub visibility: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub email: Cow<'a, str>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Emails<'a> {
+    #[serde(borrow)]
+    pub email: Option<Vec<Email<'a>>>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Address<'a> {
+    #[serde(rename = "@visibility")]
+    #[serde(borrow, default, deserialize_with = "borrow_option")]
+    pub visibility: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub country: Cow<'a, str>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Addresses<'a> {
+    #[serde(borrow)]
+    pub address: Option<Vec<Address<'a>>>,
+}
+
+/// Biographical details of a person
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Person<'a> {
+    #[serde(borrow)]
+    pub name: PersonName<'a>,
+    #[serde(rename = "other-names", borrow)]
+    pub other_names: Option<OtherNames<'a>>,
+    #[serde(rename = "external-identifiers", borrow)]
+    pub external_identifiers: Option<PersonExternalIdentifiers<'a>>,
+    #[serde(rename = "researcher-urls", borrow)]
+    pub researcher_urls: Option<ResearcherUrls<'a>>,
+    #[serde(borrow)]
+    pub keywords: Option<Keywords<'a>>,
+    #[serde(borrow)]
+    pub emails: Option<Emails<'a>>,
+    #[serde(borrow)]
+    pub addresses: Option<Addresses<'a>>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct OrgIdentifier<'a> {
+    #[serde(alias = "disambiguated-organization-identifier", borrow)]
+    pub identifier: Cow<'a, str>,
+    #[serde(alias = "disambiguation-source", borrow)]
+    pub source: Cow<'a, str>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct OrgAddress<'a> {
+    #[serde(borrow, default, deserialize_with = "borrow_option")]
+    pub city: Option<Cow<'a, str>>,
+    #[serde(borrow, default, deserialize_with = "borrow_option")]
+    pub region: Option<Cow<'a, str>>,
+    #[serde(borrow, default, deserialize_with = "borrow_option")]
+    pub country: Option<Cow<'a, str>>,
+}
+
+/// An organization, optionally with a disambiguated (e.g. ROR) identifier
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Organization<'a> {
+    #[serde(borrow)]
+    pub name: Cow<'a, str>,
+    #[serde(borrow)]
+    pub address: Option<OrgAddress<'a>>,
+    #[serde(alias = "disambiguated-organization", borrow)]
+    pub identifier: Option<OrgIdentifier<'a>>,
+}
+
+/// An employment (i.e. affiliation) of a person
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Employment<'a> {
+    #[serde(rename = "@visibility")]
+    #[serde(borrow, default, deserialize_with = "borrow_option")]
+    pub visibility: Option<Cow<'a, str>>,
+    /// Present if the employment ended, i.e. for past employments
+    #[serde(alias = "end-date")]
+    pub end: Option<()>,
+    #[serde(borrow)]
+    pub organization: Organization<'a>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct AffiliationGroup<'a> {
+    #[serde(alias = "employment-summary", borrow)]
+    pub employment: Employment<'a>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Employments<'a> {
+    #[serde(alias = "affiliation-group", borrow)]
+    pub employment: Option<Vec<AffiliationGroup<'a>>>,
+}
+
+/// The activities of a record, of which only employments are used. The other
+/// sections (works in particular, which make up most of the XML of prolific
+/// researchers' records) are skipped by the deserializer, or blanked out
+/// beforehand by [`Record::from_xml_mut`].
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Activities<'a> {
+    #[serde(borrow)]
+    pub employments: Employments<'a>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct PrimaryRecord<'a> {
+    #[serde(rename = "orcid-identifier", borrow)]
+    pub identifier: Identifier<'a>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Deprecated<'a> {
+    #[serde(rename = "primary-record", borrow)]
+    pub primary_record: Option<PrimaryRecord<'a>>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct History<'a> {
+    #[serde(rename = "deactivation-date")]
+    #[serde(borrow, default, deserialize_with = "borrow_option")]
+    pub deactivation_date: Option<Cow<'a, str>>,
+}
+
+/// A record of the ORCiD Public Data Summaries file, with only public elements
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Record<'a> {
+    #[serde(alias = "orcid-identifier", borrow)]
+    pub identifier: Identifier<'a>,
+    // Deprecated (i.e. merged) records have no person or activities
+    #[serde(default, borrow)]
+    pub person: Person<'a>,
+    #[serde(alias = "activities-summary", default, borrow)]
+    pub activities: Activities<'a>,
+    #[serde(borrow)]
+    pub deprecated: Option<Deprecated<'a>>,
+    #[serde(borrow)]
+    pub history: Option<History<'a>>,
+    /// Status of records that are only available as an ORCiD API error
+    #[serde(skip)]
+    pub(crate) error_status: Option<RecordStatus>,
+}
+
+/// An ORCiD API error, which the data dumps contain in place of locked,
+/// deprecated or deactivated records
+#[derive(Debug, Default, Deserialize)]
+struct OrcidError {
+    #[serde(rename = "response-code")]
+    response_code: Option<u16>,
+    #[serde(rename = "error-code")]
+    error_code: Option<u16>,
+    #[serde(rename = "developer-message")]
+    developer_message: Option<String>,
+    #[serde(rename = "user-message")]
+    user_message: Option<String>,
+}
+
+/// Status of a record
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordStatus {
+    Active,
+    /// Merged into the `primary` record
+    Deprecated {
+        primary: Option<String>,
+    },
+    Locked,
+    Deactivated,
+}
+
+impl std::fmt::Display for RecordStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordStatus::Active => write!(f, "active"),
+            RecordStatus::Deprecated { primary: Some(p) } => write!(f, "deprecated (see {})", p),
+            RecordStatus::Deprecated { primary: None } => write!(f, "deprecated"),
+            RecordStatus::Locked => write!(f, "locked"),
+            RecordStatus::Deactivated => write!(f, "deactivated"),
+        }
+    }
+}
+
+/// Whether an element with the given `visibility` attribute is public. Elements
+/// without the attribute are part of the public data (i.e. public) as well.
+fn is_public(visibility: &Option<Cow<str>>) -> bool {
+    visibility.as_deref().is_none_or(|v| v == "public")
+}
+
+impl Record<'_> {
+    /// Whether the record is active, or deprecated, locked or deactivated
+    pub fn status(&self) -> RecordStatus {
+        if let Some(status) = &self.error_status {
+            return status.clone();
+        }
+        if let Some(deprecated) = &self.deprecated {
+            let primary = deprecated.primary_record.as_ref();
+            return RecordStatus::Deprecated {
+                primary: primary.map(|p| p.identifier.path.to_string()),
+            };
+        }
+        let history = self.history.as_ref();
+        if history.is_some_and(|h| h.deactivation_date.is_some()) {
+            return RecordStatus::Deactivated;
+        }
+        RecordStatus::Active
+    }
+
+    /// Drop all elements that are not public
+    fn retain_public(&mut self) {
+        let person = &mut self.person;
+        if !is_public(&person.name.visibility) {
+            person.name = PersonName::default();
+        }
+        if let Some(names) = person.other_names.as_mut().and_then(|o| o.names.as_mut()) {
+            names.retain(|n| is_public(&n.visibility));
+        }
+        let external_identifiers = person.external_identifiers.as_mut();
+        if let Some(ids) = external_identifiers.and_then(|e| e.identifiers.as_mut()) {
+            ids.retain(|i| is_public(&i.visibility));
+        }
+        if let Some(urls) = person
+            .researcher_urls
+            .as_mut()
+            .and_then(|u| u.urls.as_mut())
+        {
+            urls.retain(|u| is_public(&u.visibility));
+        }
+        if let Some(keywords) = person.keywords.as_mut().and_then(|k| k.keyword.as_mut()) {
+            keywords.retain(|k| is_public(&k.visibility));
+        }
+        if let Some(emails) = person.emails.as_mut().and_then(|e| e.email.as_mut()) {
+            emails.retain(|e| is_public(&e.visibility));
+        }
+        if let Some(addresses) = person.addresses.as_mut().and_then(|a| a.address.as_mut()) {
+            addresses.retain(|a| is_public(&a.visibility));
+        }
+        if let Some(employments) = self.activities.employments.employment.as_mut() {
+            employments.retain(|a| is_public(&a.employment.visibility));
+        }
+    }
+}
+
+/// Values that can be detached from the XML they borrow from
+trait IntoOwned {
+    type Owned;
+
+    fn into_owned(self) -> Self::Owned;
+}
+
+impl IntoOwned for Cow<'_, str> {
+    type Owned = Cow<'static, str>;
+
+    fn into_owned(self) -> Cow<'static, str> {
+        Cow::Owned(Cow::into_owned(self))
+    }
+}
+
+impl<T: IntoOwned> IntoOwned for Option<T> {
+    type Owned = Option<T::Owned>;
+
+    fn into_owned(self) -> Self::Owned {
+        self.map(IntoOwned::into_owned)
+    }
+}
+
+impl<T: IntoOwned> IntoOwned for Vec<T> {
+    type Owned = Vec<T::Owned>;
+
+    fn into_owned(self) -> Self::Owned {
+        self.into_iter().map(IntoOwned::into_owned).collect()
+    }
+}
+
+impl IntoOwned for () {
+    type Owned = ();
+
+    fn into_owned(self) {}
+}
+
+impl IntoOwned for RecordStatus {
+    type Owned = RecordStatus;
+
+    fn into_owned(self) -> Self::Owned {
+        self
+    }
+}
+
+macro_rules! impl_into_owned {
+    ($($name:ident { $($field:ident),* $(,)? })*) => {
+        $(
+            impl IntoOwned for $name<'_> {
+                type Owned = $name<'static>;
+
+                fn into_owned(self) -> Self::Owned {
+                    $name {
+                        $($field: IntoOwned::into_owned(self.$field)),*
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_into_owned! {
+    Identifier { uri, path }
+    PersonName { visibility, given_names, family_name, credit_name }
+    OtherName { visibility, content }
+    OtherNames { names }
+    PersonExternalIdentifier { visibility, id_type, value }
+    PersonExternalIdentifiers { identifiers }
+    ResearcherUrl { visibility, url }
+    ResearcherUrls { urls }
+    Keyword { visibility, content }
+    Keywords { keyword }
+    Email { visibility, email }
+    Emails { email }
+    Address { visibility, country }
+    Addresses { address }
+    Person {
+        name,
+        other_names,
+        external_identifiers,
+        researcher_urls,
+        keywords,
+        emails,
+        addresses,
+    }
+    OrgIdentifier { identifier, source }
+    OrgAddress { city, region, country }
+    Organization { name, address, identifier }
+    Employment { visibility, end, organization }
+    AffiliationGroup { employment }
+    Employments { employment }
+    Activities { employments }
+    PrimaryRecord { identifier }
+    Deprecated { primary_record }
+    History { deactivation_date }
+    Record { identifier, person, activities, deprecated, history, error_status }
+}
+
+impl<'a> Record<'a> {
+    /// Parse the XML of a record, keeping only public elements.
+    ///
+    /// ORCiD API errors, which the data file contains in place of locked,
+    /// deprecated or deactivated records, are parsed into a record without
+    /// any data, but with the corresponding [`status`](Record::status).
+    ///
+    /// ```
+    /// use orcid_data_toolkit::record::{Record, RecordStatus};
+    ///
+    /// let xml = std::fs::read_to_string("tests/data/alex.xml")?;
+    /// let record = Record::from_xml(&xml)?;
+    /// assert_eq!(record.identifier.path, "0000-0002-5082-6404");
+    /// assert_eq!(record.status(), RecordStatus::Active);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn from_xml(xml_content: &'a str) -> Result<Self> {
+        if root_element(xml_content).is_some_and(|root| root == "error:error") {
+            return parse_error_record(xml_content);
+        }
+        deserialize_record(xml_content)
+    }
+
+    /// Like [`from_xml`](Record::from_xml), but first blanks out the content of
+    /// the unused activities sections (works, fundings, peer reviews, etc.) in
+    /// `xml_content`, so that the deserializer doesn't have to tokenize them.
+    /// This makes parsing records with many works several times faster.
+    ///
+    /// ```
+    /// use orcid_data_toolkit::record::Record;
+    ///
+    /// let mut xml = std::fs::read_to_string("tests/data/paolo.xml")?;
+    /// let record = Record::from_xml_mut(&mut xml)?;
+    /// assert_eq!(record.person.name.family_name.as_deref(), Some("Manghi"));
+    /// assert!(!xml.contains("<work:title>"));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn from_xml_mut(xml_content: &'a mut str) -> Result<Self> {
+        for range in unused_activities(xml_content) {
+            // SAFETY: the range lies between the `>` of a start tag and the `<`
+            // of an end tag, so replacing it with ASCII keeps the string UTF-8
+            let content = unsafe { xml_content[range].as_bytes_mut() };
+            // A comment is skipped with a single search for its end, unlike
+            // whitespace, which is trimmed byte by byte
+            content.fill(b' ');
+            let len = content.len();
+            if len >= 7 {
+                content[..4].copy_from_slice(b"<!--");
+                content[len - 3..].copy_from_slice(b"-->");
+            }
+        }
+        Self::from_xml(xml_content)
+    }
+
+    /// Detach the record from the XML it was parsed from
+    pub fn into_owned(self) -> Record<'static> {
+        IntoOwned::into_owned(self)
+    }
+}
+
+/// Parse XML string into a Record, logging errors
+pub(crate) fn parse_xml(xml_content: &mut str) -> Option<Record<'_>> {
+    match Record::from_xml_mut(xml_content) {
+        Ok(record) => Some(record),
+        Err(err) => {
+            eprintln!("{:#}", err);
+            None
+        }
+    }
+}
+
+fn deserialize_record(xml_content: &str) -> Result<Record<'_>> {
+    let rd = &mut Deserializer::from_str(xml_content);
+    let mut record: Record = serde_path_to_error::deserialize(rd)
+        .map_err(|err| anyhow::anyhow!("Error parsing XML: {}", err.path()))?;
+    record.retain_public();
+    Ok(record)
+}
+
+/// Name of the root element of an XML document
+fn root_element(xml_content: &str) -> Option<&str> {
+    let mut rest = xml_content;
+    loop {
+        let start = rest.find('<')?;
+        rest = &rest[start + 1..];
+        // Skip the XML declaration, processing instructions and comments
+        if !rest.starts_with('?') && !rest.starts_with('!') {
+            let end = rest.find(|c: char| c.is_whitespace() || c == '>' || c == '/')?;
+            return Some(&rest[..end]);
+        }
+    }
+}
+
+/// Sections of `activities-summary` that are used
+const USED_ACTIVITIES: [&str; 1] = ["employments"];
+
+/// A piece of markup, as read by [`next_markup`]
+enum Markup<'a> {
+    Start(&'a str),
+    End,
+    /// Empty element tags, comments, CDATA sections and processing instructions
+    Other,
+}
+
+/// Read the markup starting at the `<` at `pos`, returning it and the offset
+/// after it. Returns `None` for malformed or unsupported markup (e.g. a DTD).
+fn next_markup(xml: &str, pos: usize) -> Option<(Markup<'_>, usize)> {
+    let rest = &xml[pos..];
+    for (open, close) in [("<!--", "-->"), ("<![CDATA[", "]]>"), ("<?", "?>")] {
+        if rest.starts_with(open) {
+            let end = memmem::find(&rest.as_bytes()[open.len()..], close.as_bytes())?;
+            return Some((Markup::Other, pos + open.len() + end + close.len()));
+        }
+    }
+    if rest.starts_with("<!") {
+        return None;
+    }
+    // Tags end at the first `>` outside of quoted attribute values
+    let mut quote = None;
+    let end = rest.bytes().position(|b| match quote {
+        Some(q) => {
+            if b == q {
+                quote = None;
+            }
+            false
+        }
+        None => {
+            if b == b'"' || b == b'\'' {
+                quote = Some(b);
+            }
+            b == b'>'
+        }
+    })?;
+    let tag = &rest[1..end];
+    let (is_end, tag) = match tag.strip_prefix('/') {
+        Some(tag) => (true, tag),
+        None => (false, tag),
+    };
+    let name_end = tag.find(|c: char| c.is_whitespace() || c == '/');
+    let name = &tag[..name_end.unwrap_or(tag.len())];
+    let markup = if is_end {
+        Markup::End
+    } else if tag.ends_with('/') {
+        Markup::Other
+    } else {
+        Markup::Start(name)
+    };
+    Some((markup, pos + end + 1))
+}
+
+/// Offset of the end tag of the element `name` whose content starts at `pos`
+fn element_end(xml: &str, name: &str, pos: usize) -> Option<usize> {
+    let end_tag = format!("</{name}");
+    let mut from = pos;
+    while let Some(found) = memmem::find(&xml.as_bytes()[from..], end_tag.as_bytes()) {
+        let end = from + found;
+        from = end + end_tag.len();
+        if !xml[from..].starts_with(|c: char| c == '>' || c.is_whitespace()) {
+            continue;
+        }
+        // Usually the content contains no markup that can hide or nest tags, so
+        // the first end tag is the element's
+        let content = &xml.as_bytes()[pos..end];
+        let start_tag = format!("<{name}");
+        if ["<!", "<?", &start_tag]
+            .iter()
+            .all(|needle| memmem::find(content, needle.as_bytes()).is_none())
+        {
+            return Some(end);
+        }
+        break;
+    }
+
+    // Otherwise read the content tag by tag
+    let mut depth = 0;
+    let mut from = pos;
+    while let Some(found) = memchr(b'<', &xml.as_bytes()[from..]) {
+        let start = from + found;
+        let (markup, end) = next_markup(xml, start)?;
+        from = end;
+        match markup {
+            Markup::Start(_) => depth += 1,
+            Markup::End if depth == 0 => return Some(start),
+            Markup::End => depth -= 1,
+            Markup::Other => {}
+        }
+    }
+    None
+}
+
+/// Local name of an element, i.e. without its namespace prefix
+fn local_name(name: &str) -> &str {
+    name.rsplit_once(':').map_or(name, |(_, local)| local)
+}
+
+/// Ranges of the content of the unused sections of a record's
+/// `activities-summary`, or none if the XML can't be read
+fn unused_activities(xml: &str) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    let mut depth = 0;
+    let mut in_activities = false;
+    let mut from = 0;
+    while let Some(found) = memchr(b'<', &xml.as_bytes()[from..]) {
+        let Some((markup, end)) = next_markup(xml, from + found) else {
+            return vec![];
+        };
+        from = end;
+        match markup {
+            Markup::Start(name) => {
+                depth += 1;
+                if depth == 2 && local_name(name) == "activities-summary" {
+                    in_activities = true;
+                } else if depth == 3
+                    && in_activities
+                    && !USED_ACTIVITIES.contains(&local_name(name))
+                {
+                    let Some(content_end) = element_end(xml, name, end) else {
+                        return vec![];
+                    };
+                    ranges.push(end..content_end);
+                    from = content_end;
+                }
+            }
+            Markup::End => {
+                depth -= 1;
+                if in_activities && depth == 1 {
+                    break;
+                }
+            }
+            Markup::Other => {}
+        }
+    }
+    ranges
+}
+
+/// ORCiD iDs, as mentioned in error messages
+static ORCID_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\d{4}-\d{4}-\d{4}-\d{3}[\dX]").expect("valid regex"));
+
+/// ORCiD API error codes of inactive records
+const ERROR_CODE_DEPRECATED: u16 = 9007;
+const ERROR_CODE_LOCKED: u16 = 9018;
+const ERROR_CODE_DEACTIVATED: u16 = 9044;
+
+fn parse_error_record(xml_content: &str) -> Result<Record<'static>> {
+    let rd = &mut Deserializer::from_str(xml_content);
+    let error: OrcidError = serde_path_to_error::deserialize(rd)
+        .map_err(|err| anyhow::anyhow!("Error parsing XML error: {}", err.path()))?;
+    let message = [&error.developer_message, &error.user_message]
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    // Messages mention the record's ORCiD iD, and the primary one for deprecated records
+    let mut orcids: Vec<&str> = vec![];
+    for m in ORCID_RE.find_iter(&message) {
+        if !orcids.contains(&m.as_str()) {
+            orcids.push(m.as_str());
+        }
+    }
+    // Classified by error code (or the HTTP status, if there is none) rather
+    // than by the wording of the messages
+    let status = match (error.error_code, error.response_code) {
+        (Some(ERROR_CODE_DEPRECATED), _) | (None, Some(301)) => RecordStatus::Deprecated {
+            primary: orcids.get(1).map(|p| p.to_string()),
+        },
+        (Some(ERROR_CODE_LOCKED), _) => RecordStatus::Locked,
+        (Some(ERROR_CODE_DEACTIVATED), _) | (None, Some(410)) => RecordStatus::Deactivated,
+        (error_code, response_code) => bail!(
+            "Unexpected ORCiD error (response code {:?}, error code {:?}): {}",
+            response_code,
+            error_code,
+            message
+        ),
+    };
+    let Some(orcid) = orcids.first() else {
+        bail!("No ORCiD iD in {} record error: {}", status, message);
+    };
+    Ok(Record {
+        identifier: Identifier {
+            uri: format!("https://orcid.org/{}", orcid).into(),
+            path: orcid.to_string().into(),
+        },
+        error_status: Some(status),
+        ..Default::default()
+    })
+}
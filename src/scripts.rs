@@ -0,0 +1,64 @@
+//! Unicode script detection and Latin transliteration of names.
+
+use clap::Args;
+use deunicode::deunicode;
+use unicode_script::{Script, UnicodeScript};
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct ScriptOptions {
+    /// Only keep names written in the given Unicode script (e.g. Latin, Cyrillic,
+    /// Han, Arabic). Can be repeated.
+    #[arg(long = "allow-script", value_name = "SCRIPT", value_parser = parse_script)]
+    pub allowed: Vec<Script>,
+
+    /// Include the Unicode scripts a name is written in as `scripts`
+    #[arg(long = "annotate-scripts")]
+    pub annotate: bool,
+
+    /// Include a Latin transliteration of non-Latin names as `name_latin`
+    #[arg(long = "transliterate")]
+    pub transliterate: bool,
+}
+
+pub(crate) fn parse_script(value: &str) -> Result<Script, String> {
+    Script::from_full_name(value)
+        .or_else(|| Script::from_short_name(value))
+        .ok_or_else(|| format!("Unknown Unicode script {:?}", value))
+}
+
+impl ScriptOptions {
+    /// Whether all `scripts` are allowed
+    pub(crate) fn is_allowed(&self, scripts: &[Script]) -> bool {
+        self.allowed.is_empty() || scripts.iter().all(|s| self.allowed.contains(s))
+    }
+}
+
+/// The distinct scripts of the letters in `values`, in order of appearance.
+///
+/// Characters shared between scripts (e.g. punctuation, combining marks) are ignored.
+pub(crate) fn scripts<'a>(values: impl IntoIterator<Item = &'a str>) -> Vec<Script> {
+    let mut scripts = vec![];
+    for c in values.into_iter().flat_map(str::chars) {
+        let script = c.script();
+        if matches!(script, Script::Common | Script::Inherited | Script::Unknown) {
+            continue;
+        }
+        if !scripts.contains(&script) {
+            scripts.push(script);
+        }
+    }
+    scripts
+}
+
+/// Transliterate `value` to Latin, unless it's already written in Latin
+pub(crate) fn transliterate(value: &str, scripts: &[Script]) -> Option<String> {
+    if scripts.iter().all(|s| *s == Script::Latin) {
+        return None;
+    }
+    // Some characters (e.g. CJK ideographs) transliterate to padded syllables
+    let latin = deunicode(value)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!latin.is_empty() && latin != value).then_some(latin)
+}
@@ -0,0 +1,213 @@
+//! Splitting the output of conversions into multiple files ("shards").
+//!
+//! For an output file `names.csv`, the shards are written to `names-00001.csv`,
+//! `names-00002.csv`, etc. and listed in the `names.manifest.json` manifest,
+//! with the number of records and the SHA-256 checksum of each of them.
+//! Checksumming is a noticeable part of converting small records into shards
+//! (see the `sha256` benchmarks).
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use clap::Args;
+use sha2::{Digest, Sha256};
+
+use crate::atomic_file::AtomicFile;
+use crate::error::ErrorContext;
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct ShardOptions {
+    /// Split the output into files of (at most) the given number of records,
+    /// e.g. `names-00001.csv`, `names-00002.csv`, etc.
+    #[arg(
+        long = "shard-size",
+        value_name = "RECORDS",
+        conflicts_with = "shards",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub shard_size: Option<u64>,
+
+    /// Split the output into the given number of files (at most 256),
+    /// assigning each record to one of them by a hash of its ORCiD iD
+    #[arg(
+        long = "shards",
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..=MAX_SHARDS)
+    )]
+    pub shards: Option<u64>,
+}
+
+/// Maximum number of `--shards`, which are all kept open until the conversion
+/// completes (with two file descriptors each)
+pub(crate) const MAX_SHARDS: u64 = 256;
+
+/// How records are assigned to shards
+#[derive(Debug, Clone, Copy)]
+enum Sharding {
+    /// A new shard is started after every `size` records
+    Count { size: u64 },
+    /// Records are assigned to one of `count` shards by their ORCiD iD
+    Hash { count: u64 },
+}
+
+struct Shard {
+    file_name: String,
+    file: AtomicFile,
+    writer: BufWriter<File>,
+    hasher: Sha256,
+    records: u64,
+}
+
+#[derive(serde::Serialize)]
+struct ManifestShard {
+    file: String,
+    records: u64,
+    sha256: String,
+}
+
+#[derive(serde::Serialize)]
+struct Manifest {
+    /// "count" or "hash"
+    sharding: &'static str,
+    records: u64,
+    shards: Vec<ManifestShard>,
+}
+
+/// Output split into shards, see the module documentation
+pub(crate) struct Shards {
+    output_file: PathBuf,
+    sharding: Sharding,
+    shards: Vec<Shard>,
+}
+
+impl Shards {
+    /// Create the shards of `output_file`, if sharding was requested
+    pub(crate) fn create(output_file: &Path, options: &ShardOptions) -> Result<Option<Self>> {
+        let sharding = match (options.shard_size, options.shards) {
+            (Some(size), _) => Sharding::Count { size },
+            (None, Some(count)) => Sharding::Hash { count },
+            (None, None) => return Ok(None),
+        };
+        if output_file.to_str() == Some("-") {
+            bail!("Sharded output needs an --output-file to name the shards after");
+        }
+        let mut shards = Shards {
+            output_file: output_file.to_path_buf(),
+            sharding,
+            shards: vec![],
+        };
+        if let Sharding::Hash { count } = sharding {
+            for _ in 0..count {
+                shards.add_shard()?;
+            }
+        }
+        Ok(Some(shards))
+    }
+
+    /// Path of the shard with the given (1-based) number
+    fn shard_path(&self, number: usize) -> PathBuf {
+        let stem = self.output_file.file_stem().unwrap_or_default();
+        let mut file_name = stem.to_os_string();
+        file_name.push(format!("-{:05}", number));
+        if let Some(extension) = self.output_file.extension() {
+            file_name.push(".");
+            file_name.push(extension);
+        }
+        self.output_file.with_file_name(file_name)
+    }
+
+    fn manifest_path(&self) -> PathBuf {
+        let mut file_name = self
+            .output_file
+            .file_stem()
+            .unwrap_or_default()
+            .to_os_string();
+        file_name.push(".manifest.json");
+        self.output_file.with_file_name(file_name)
+    }
+
+    fn add_shard(&mut self) -> Result<()> {
+        let path = self.shard_path(self.shards.len() + 1);
+        let file = AtomicFile::create(&path)?;
+        self.shards.push(Shard {
+            file_name: path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            writer: BufWriter::new(file.file().try_clone()?),
+            file,
+            hasher: Sha256::new(),
+            records: 0,
+        });
+        Ok(())
+    }
+
+    /// Write the (encoded) line of the record with the given ORCiD iD
+    pub(crate) fn write_line(&mut self, orcid: &str, line: &[u8]) -> Result<()> {
+        let idx = match self.sharding {
+            Sharding::Count { size } => {
+                if self.shards.last().is_none_or(|s| s.records >= size) {
+                    self.add_shard()?;
+                }
+                self.shards.len() - 1
+            }
+            Sharding::Hash { count } => (orcid_hash(orcid) % count) as usize,
+        };
+        let shard = &mut self.shards[idx];
+        shard
+            .writer
+            .write_all(line)
+            .output_context(|| format!("Error writing file {}", shard.file_name))?;
+        shard.hasher.update(line);
+        shard.records += 1;
+        Ok(())
+    }
+
+    /// Flush all shards and write the manifest, returning its path
+    pub(crate) fn finish(self) -> Result<PathBuf> {
+        let path = self.manifest_path();
+        let mut manifest = Manifest {
+            sharding: match self.sharding {
+                Sharding::Count { .. } => "count",
+                Sharding::Hash { .. } => "hash",
+            },
+            records: 0,
+            shards: vec![],
+        };
+        for mut shard in self.shards {
+            shard
+                .writer
+                .flush()
+                .output_context(|| format!("Error writing file {}", shard.file_name))?;
+            shard.file.commit()?;
+            manifest.records += shard.records;
+            manifest.shards.push(ManifestShard {
+                sha256: format!("{:x}", shard.hasher.finalize()),
+                file: shard.file_name,
+                records: shard.records,
+            });
+        }
+        let file = AtomicFile::create(&path)?;
+        let mut writer = BufWriter::new(file.file().try_clone()?);
+        serde_json::to_writer_pretty(&mut writer, &manifest)?;
+        writer.write_all(b"\n")?;
+        writer
+            .flush()
+            .output_context(|| format!("Error writing file {}", path.display()))?;
+        file.commit()?;
+        Ok(path)
+    }
+}
+
+/// Stable hash of an ORCiD iD, i.e. the first 8 bytes of its SHA-256 digest
+fn orcid_hash(orcid: &str) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(orcid.as_bytes());
+    let digest = hasher.finalize();
+    u64::from_be_bytes(digest[..8].try_into().expect("8 bytes"))
+}
@@ -0,0 +1,156 @@
+//! Rule-based quality checks for person names.
+
+use std::{fmt, path::PathBuf};
+
+use clap::{Args, ValueEnum};
+
+/// Values commonly entered instead of an actual name. Real names that happen
+/// to be common words (e.g. the surnames "Na" or "Name") are left out.
+const PLACEHOLDERS: [&str; 12] = [
+    "test", "testing", "n/a", "none", "null", "unknown", "xxx", "asdf", ".", "-", "?", "_",
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, ValueEnum)]
+pub enum NameCheck {
+    /// Name written in all capital letters
+    AllCaps,
+    /// Name written in all lowercase letters
+    AllLowercase,
+    /// Email address in a name field
+    Email,
+    /// URL in a name field
+    Url,
+    /// Digits in a name field
+    Digits,
+    /// Same word repeated within a name field, e.g. "John John"
+    RepeatedTokens,
+    /// Placeholder value, e.g. "test", "n/a" or "."
+    Placeholder,
+    /// Given name equal to the family name
+    GivenEqualsFamily,
+}
+
+impl fmt::Display for NameCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no skipped variants");
+        f.write_str(value.get_name())
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum NameCheckMode {
+    /// Don't check names
+    #[default]
+    Off,
+    /// Leave out records with flagged names
+    Reject,
+    /// Keep records with flagged names, only reporting them in the review file
+    Review,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct NameChecks {
+    /// Check names for common quality problems
+    #[arg(long = "name-checks", value_enum, default_value_t = NameCheckMode::Off)]
+    pub mode: NameCheckMode,
+
+    /// Name check to skip. Can be repeated.
+    #[arg(long = "skip-name-check", value_enum)]
+    pub skipped: Vec<NameCheck>,
+
+    /// Path to a CSV file to write flagged records (and the checks they failed) to
+    #[arg(long = "review-file")]
+    pub review_file: Option<PathBuf>,
+}
+
+/// A record flagged by the name checks
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct ReviewEntry {
+    pub orcid: String,
+    pub checks: String,
+    pub rejected: bool,
+    pub given_name: String,
+    pub family_name: String,
+    pub name: String,
+}
+
+impl NameChecks {
+    /// Return the (enabled) checks that the given/family names fail
+    pub(crate) fn check(&self, given_name: &str, family_name: &str) -> Vec<NameCheck> {
+        if self.mode == NameCheckMode::Off {
+            return vec![];
+        }
+        let fields = [given_name, family_name];
+        let fields = fields.iter().filter(|f| !f.trim().is_empty());
+        let mut failed = vec![];
+        let mut flag = |check: NameCheck, failed_check: bool| {
+            if failed_check && !self.skipped.contains(&check) && !failed.contains(&check) {
+                failed.push(check);
+            }
+        };
+        for field in fields {
+            flag(
+                NameCheck::AllCaps,
+                is_single_case(field, char::is_uppercase),
+            );
+            flag(
+                NameCheck::AllLowercase,
+                is_single_case(field, char::is_lowercase),
+            );
+            flag(NameCheck::Email, looks_like_email(field));
+            flag(NameCheck::Url, looks_like_url(field));
+            flag(NameCheck::Digits, field.chars().any(|c| c.is_numeric()));
+            flag(NameCheck::RepeatedTokens, has_repeated_tokens(field));
+            flag(NameCheck::Placeholder, is_placeholder(field));
+        }
+        let given = given_name.trim().to_lowercase();
+        let family = family_name.trim().to_lowercase();
+        flag(
+            NameCheck::GivenEqualsFamily,
+            !given.is_empty() && given == family,
+        );
+        failed
+    }
+}
+
+/// Whether all cased letters satisfy `is_case`, ignoring short values (e.g.
+/// initials) and scripts without case
+fn is_single_case(value: &str, is_case: fn(char) -> bool) -> bool {
+    let cased: Vec<char> = value
+        .chars()
+        .filter(|c| c.is_uppercase() || c.is_lowercase())
+        .collect();
+    cased.len() > 2 && cased.into_iter().all(is_case)
+}
+
+fn looks_like_email(value: &str) -> bool {
+    value.split_whitespace().any(|token| {
+        token
+            .split_once('@')
+            .is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.'))
+    })
+}
+
+fn looks_like_url(value: &str) -> bool {
+    let value = value.to_lowercase();
+    ["http://", "https://", "www."]
+        .iter()
+        .any(|prefix| value.contains(prefix))
+}
+
+fn has_repeated_tokens(value: &str) -> bool {
+    let tokens: Vec<String> = value
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|t| t.chars().filter(|c| c.is_alphabetic()).count() > 1)
+        .map(str::to_lowercase)
+        .collect();
+    tokens
+        .iter()
+        .enumerate()
+        .any(|(idx, token)| tokens[..idx].contains(token))
+}
+
+fn is_placeholder(value: &str) -> bool {
+    let value = value.trim().to_lowercase();
+    PLACEHOLDERS.contains(&value.as_str())
+}
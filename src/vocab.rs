@@ -0,0 +1,123 @@
+//! Entries of InvenioRDM names vocabulary datastreams, as imported with
+//! `invenio vocabularies import --vocabulary names` (YAML or JSON Lines).
+//!
+//! Each entry is a name as in `convert --format json`, keyed by its ORCiD iD
+//! as `id`. The transliteration and scripts of a name, which the names
+//! vocabulary has no fields for, are kept as (string) `props`.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+
+use crate::{NameAffiliation, NameIdentifier, NameJson};
+
+#[derive(serde::Serialize)]
+struct VocabEntry<'a> {
+    id: &'a str,
+    given_name: &'a str,
+    family_name: &'a str,
+    name: &'a str,
+    identifiers: &'a [NameIdentifier],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    affiliations: Option<&'a [NameAffiliation]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alternative_names: Option<&'a [String]>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    props: BTreeMap<&'static str, String>,
+}
+
+impl<'a> VocabEntry<'a> {
+    fn new(orcid: &'a str, name: &'a NameJson) -> Self {
+        let mut props = BTreeMap::new();
+        if let Some(name_latin) = &name.name_latin {
+            props.insert("name_latin", name_latin.clone());
+        }
+        if let Some(scripts) = &name.scripts {
+            props.insert("scripts", scripts.join(","));
+        }
+        VocabEntry {
+            id: orcid,
+            given_name: &name.given_name,
+            family_name: &name.family_name,
+            name: &name.name,
+            identifiers: &name.identifiers,
+            affiliations: name.affiliations.as_deref(),
+            alternative_names: name.alternative_names.as_deref(),
+            props,
+        }
+    }
+}
+
+/// Encode a name as a line of a JSON Lines datastream
+pub(crate) fn encode_jsonl(orcid: &str, name: &NameJson) -> Result<String> {
+    Ok(serde_json::to_string(&VocabEntry::new(orcid, name))? + "\n")
+}
+
+/// Encode a name as an item of the top-level sequence of a YAML datastream.
+///
+/// The items of a file can be concatenated (or split into shards) freely. All
+/// strings are double-quoted, so that no value is read as another type (e.g.
+/// a family name "No" as `false`).
+pub(crate) fn encode_yaml(orcid: &str, name: &NameJson) -> Result<String> {
+    let entry = VocabEntry::new(orcid, name);
+    let mut yaml = format!("- id: {}\n", quote(entry.id));
+    yaml += &format!("  given_name: {}\n", quote(entry.given_name));
+    yaml += &format!("  family_name: {}\n", quote(entry.family_name));
+    yaml += &format!("  name: {}\n", quote(entry.name));
+    yaml += "  identifiers:\n";
+    for identifier in entry.identifiers {
+        yaml += &format!("    - scheme: {}\n", quote(&identifier.scheme));
+        yaml += &format!("      identifier: {}\n", quote(&identifier.identifier));
+    }
+    if let Some(affiliations) = entry.affiliations {
+        yaml += "  affiliations:\n";
+        for affiliation in affiliations {
+            match &affiliation.id {
+                Some(id) => {
+                    yaml += &format!("    - id: {}\n", quote(id));
+                    yaml += &format!("      name: {}\n", quote(&affiliation.name));
+                }
+                None => yaml += &format!("    - name: {}\n", quote(&affiliation.name)),
+            }
+        }
+    }
+    if let Some(alternative_names) = entry.alternative_names {
+        yaml += "  alternative_names:\n";
+        for alternative_name in alternative_names {
+            yaml += &format!("    - {}\n", quote(alternative_name));
+        }
+    }
+    if !entry.props.is_empty() {
+        yaml += "  props:\n";
+        for (key, value) in &entry.props {
+            yaml += &format!("    {}: {}\n", key, quote(value));
+        }
+    }
+    Ok(yaml)
+}
+
+/// Quote a string as a YAML double-quoted scalar, escaping the characters
+/// that aren't printable in YAML (e.g. C1 controls or U+FFFE), byte order
+/// marks, and those that YAML 1.1 parsers such as PyYAML read as line breaks
+/// (U+0085, U+2028 and U+2029)
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            ' '..='~'
+            | '\u{A0}'..='\u{2027}'
+            | '\u{202A}'..='\u{FEFE}'
+            | '\u{FF00}'..='\u{FFFD}'
+            | '\u{10000}'.. => quoted.push(c),
+            c => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
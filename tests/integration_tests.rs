@@ -1,10 +1,97 @@
 use anyhow::Result;
 use assert_cmd::prelude::*;
-use std::process::Command;
+use flate2::{write::GzEncoder, Compression};
+use orcid_data_toolkit::{extract_tgz, ExtractFormat, ExtractOptions};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeSet,
+    ffi::OsStr,
+    fs::File,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// All fixtures that are valid ORCiD records, with their ORCiD iD
+const FIXTURES: [(&str, &str); 6] = [
+    ("tests/data/alex.xml", "0000-0002-5082-6404"),
+    ("tests/data/duplicate-orgs.xml", "0000-0001-7091-3022"),
+    ("tests/data/jose.xml", "0000-0002-0816-7126"),
+    ("tests/data/lars.xml", "0000-0001-8135-3489"),
+    ("tests/data/no-family-name.xml", "0000-0003-0500-3000"),
+    ("tests/data/paolo.xml", "0000-0001-7291-3210"),
+];
+
+/// Build a `.tar.gz` laid out like the ORCiD Public Data Summaries file
+fn build_tgz(name: &str, fixtures: &[(&str, &str)]) -> Result<PathBuf> {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{name}.tar.gz"));
+    let encoder = GzEncoder::new(File::create(&path)?, Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    let mut dirs = BTreeSet::new();
+    for (fixture, orcid) in fixtures {
+        // Each directory precedes its first record, as in the real file
+        let dir = format!("ORCID_summaries/{}/", &orcid[16..]);
+        if dirs.insert(dir.clone()) {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_mode(0o755);
+            header.set_size(0);
+            builder.append_data(&mut header, &dir, std::io::empty())?;
+        }
+        builder.append_path_with_name(fixture, format!("{dir}{orcid}.xml"))?;
+    }
+    builder.into_inner()?.finish()?;
+    Ok(path)
+}
+
+/// Run the binary with `args`, asserting success and returning stdout lines
+fn stdout_lines(args: &[&OsStr]) -> Result<Vec<String>> {
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args(args)
+        .output()?;
+    assert!(output.status.success(), "{:?}", output);
+    Ok(String::from_utf8(output.stdout)?
+        .lines()
+        .map(String::from)
+        .collect())
+}
+
+/// Convert an XML fixture to JSON with extra `args`
+fn convert(fixture: &str, args: &[&str]) -> Result<serde_json::Value> {
+    let mut all_args = vec!["convert", "--input-file", fixture, "--format", "json"];
+    all_args.extend(args);
+    let args: Vec<&OsStr> = all_args.iter().map(OsStr::new).collect();
+    Ok(serde_json::from_str(&stdout_lines(&args)?.join("\n"))?)
+}
+
+/// Run `extract` on `input` with extra `args`, returning stdout lines
+fn extract(input: &Path, args: &[&str]) -> Result<Vec<String>> {
+    let mut all_args = vec![
+        "extract".as_ref(),
+        "--input-file".as_ref(),
+        input.as_os_str(),
+    ];
+    all_args.extend(args.iter().map(OsStr::new));
+    stdout_lines(&all_args)
+}
+
+/// Extract the organization IDs of `input` with a de-duplication budget of 0,
+/// which the CLI doesn't accept, to spill every identifier into its own run
+fn extract_spilled(input: &Path, name: &str) -> Result<Vec<String>> {
+    let output = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{name}.jsonl"));
+    let options = ExtractOptions {
+        dedup_memory: Some(0),
+        ..Default::default()
+    };
+    extract_tgz(input, &output, &ExtractFormat::OrgIDs, &options)?;
+    Ok(std::fs::read_to_string(output)?
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
 
 #[test]
 fn convert_xml() -> Result<()> {
-    let mut cmd = Command::cargo_bin("orcid-data-toolkit")?;
+    let mut cmd = Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"));
 
     let pred = r#"{
   "$schema": "local://names/name-v1.0.0.json",
@@ -35,3 +122,1501 @@ fn convert_xml() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn convert_json_tgz() -> Result<()> {
+    // Each object of a `.tar.gz` is written on its own line
+    let input = build_tgz("convert-json", &FIXTURES)?;
+    let lines = stdout_lines(&[
+        "convert".as_ref(),
+        "--input-file".as_ref(),
+        input.as_ref(),
+        "--format".as_ref(),
+        "json".as_ref(),
+    ])?;
+    let orcids: BTreeSet<_> = lines
+        .iter()
+        .map(|line| {
+            let name: serde_json::Value = serde_json::from_str(line)?;
+            Ok(name["identifiers"][0]["identifier"].to_string())
+        })
+        .collect::<Result<_>>()?;
+    assert_eq!(orcids.len(), FIXTURES.len());
+
+    Ok(())
+}
+
+#[test]
+fn convert_skips_unused_sections() -> Result<()> {
+    // Unused sections are skipped as XML elements, whatever their prefix and
+    // the markup in comments and CDATA sections
+    let xml = std::fs::read_to_string("tests/data/alex.xml")?
+        .replace(
+            "<activities:works path=\"/0000-0002-5082-6404/works\">",
+            "<activities:works path=\"/0000-0002-5082-6404/works\">\
+             <!-- </activities:works></activities:activities-summary> -->\
+             <![CDATA[</activities:works>]]>",
+        )
+        .replace("activities:", "act:");
+    let input = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("unused-sections.xml");
+    std::fs::write(&input, xml)?;
+    assert_eq!(
+        convert(input.to_str().unwrap(), &[])?,
+        convert("tests/data/alex.xml", &[])?
+    );
+
+    // Blanking out unused sections before parsing gives the same records
+    use orcid_data_toolkit::record::Record;
+    for path in [input.as_path(), "tests/data/paolo.xml".as_ref()] {
+        let xml = std::fs::read_to_string(path)?;
+        let mut blanked = xml.clone();
+        assert_eq!(Record::from_xml_mut(&mut blanked)?, Record::from_xml(&xml)?);
+        assert_eq!(blanked.len(), xml.len());
+        assert!(!blanked.contains("<work:title>"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn convert_invenio_vocab() -> Result<()> {
+    let mut cmd = Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"));
+    let pred = r#"- id: "0000-0003-0500-3019"
+  given_name: "Олексій"
+  family_name: "Штепа"
+  name: "Штепа, Олексій"
+  identifiers:
+    - scheme: "orcid"
+      identifier: "0000-0003-0500-3019"
+  props:
+    name_latin: "Shtepa, Oleksii"
+    scripts: "Cyrillic"
+"#;
+    cmd.args(["convert", "--input-file", "tests/data/cyrillic-name.xml"])
+        .args(["--format", "invenio-vocab-yaml"])
+        .args(["--transliterate", "--annotate-scripts"])
+        .assert()
+        .success()
+        .stdout(pred);
+
+    // Characters that YAML parsers reject or read as line breaks are escaped
+    let xml = std::fs::read_to_string("tests/data/alex.xml")?
+        .replace(">Alex<", ">A\u{85}l\u{9F}e\u{2028}x\u{FEFF}\t\"\\<");
+    let input = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("yaml-escapes.xml");
+    std::fs::write(&input, xml)?;
+    let lines = stdout_lines(&[
+        "convert".as_ref(),
+        "--input-file".as_ref(),
+        input.as_ref(),
+        "--format".as_ref(),
+        "invenio-vocab-yaml".as_ref(),
+        "--filter-name".as_ref(),
+        ".".as_ref(),
+    ])?;
+    assert_eq!(
+        lines[1],
+        r#"  given_name: "A\u0085l\u009Fe\u2028x\uFEFF\t\"\\""#
+    );
+
+    // Entries of a `.tar.gz` are streamed, one per line for JSON Lines
+    let input = build_tgz("convert-invenio-vocab", &FIXTURES)?;
+    let lines = stdout_lines(&[
+        "convert".as_ref(),
+        "--input-file".as_ref(),
+        input.as_ref(),
+        "--format".as_ref(),
+        "invenio-vocab-jsonl".as_ref(),
+        "--orgs-mapping".as_ref(),
+        "tests/data/org-mapping.csv".as_ref(),
+    ])?;
+    assert_eq!(lines.len(), FIXTURES.len());
+    let entry: serde_json::Value = serde_json::from_str(&lines[0])?;
+    assert_eq!(entry["id"], "0000-0002-5082-6404");
+    assert_eq!(entry["affiliations"][0]["id"], "01ggx4157");
+    assert!(entry.get("$schema").is_none());
+    assert!(entry.get("props").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn convert_es_bulk() -> Result<()> {
+    let input = build_tgz("convert-es-bulk", &FIXTURES)?;
+    let output = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("names.es-bulk.ndjson");
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args(["convert", "--format", "es-bulk", "--sort-by", "orcid"])
+        .args(["--orgs-mapping", "tests/data/org-mapping.csv"])
+        .args(["--timestamp", "2024-10-01T00:00:00Z"])
+        .args(["--es-index", "invenio-names-name-v1.0.0"])
+        .arg("--input-file")
+        .arg(&input)
+        .arg("--output-file")
+        .arg(&output)
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(&output)?,
+        std::fs::read_to_string("tests/data/names.es-bulk.ndjson")?
+    );
+
+    // Documents are indexed by the `id` of their `names_metadata` row
+    let lines = stdout_lines(&[
+        "convert".as_ref(),
+        "--input-file".as_ref(),
+        "tests/data/alex.xml".as_ref(),
+        "--format".as_ref(),
+        "es-bulk".as_ref(),
+    ])?;
+    assert_eq!(lines.len(), 2);
+    let action: serde_json::Value = serde_json::from_str(&lines[0])?;
+    let document: serde_json::Value = serde_json::from_str(&lines[1])?;
+    assert_eq!(action["index"]["_index"], "names-name-v1.0.0");
+    assert_eq!(action["index"]["_id"], document["id"]);
+    assert_eq!(document["pid"], "0000-0002-5082-6404");
+    assert_eq!(document["created"], document["updated"]);
+
+    Ok(())
+}
+
+#[test]
+fn convert_rdf() -> Result<()> {
+    let convert_paolo = |format: &str| {
+        stdout_lines(&[
+            "convert".as_ref(),
+            "--input-file".as_ref(),
+            "tests/data/paolo.xml".as_ref(),
+            "--format".as_ref(),
+            format.as_ref(),
+            "--orgs-mapping".as_ref(),
+            "tests/data/org-mapping.csv".as_ref(),
+        ])
+    };
+
+    // Resolved affiliations point at ROR URIs, others are blank nodes
+    let person = "<https://orcid.org/0000-0001-7291-3210>";
+    let identifier = "_:orcid-0000-0001-7291-3210-identifier-1";
+    let organization = "_:orcid-0000-0001-7291-3210-affiliation-1";
+    let triples = [
+        format!("{person} <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <https://schema.org/Person> ."),
+        format!("{person} <https://schema.org/givenName> \"Paolo\" ."),
+        format!("{person} <https://schema.org/familyName> \"Manghi\" ."),
+        format!("{person} <https://schema.org/name> \"Manghi, Paolo\" ."),
+        format!("{person} <https://schema.org/identifier> {identifier} ."),
+        format!("{identifier} <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <https://schema.org/PropertyValue> ."),
+        format!("{identifier} <https://schema.org/propertyID> \"orcid\" ."),
+        format!("{identifier} <https://schema.org/value> \"0000-0001-7291-3210\" ."),
+        format!("{person} <https://schema.org/affiliation> {organization} ."),
+        format!("{organization} <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <https://schema.org/Organization> ."),
+        format!("{organization} <https://schema.org/name> \"OpenAIRE AMKE\" ."),
+        format!("{person} <https://schema.org/affiliation> <https://ror.org/04zaypm56> ."),
+    ];
+    assert_eq!(convert_paolo("ntriples")?, triples);
+
+    let lines = convert_paolo("jsonld")?;
+    assert_eq!(lines.len(), 1);
+    let person: serde_json::Value = serde_json::from_str(&lines[0])?;
+    assert_eq!(
+        person,
+        serde_json::json!({
+            "@context": {"@vocab": "https://schema.org/"},
+            "@id": "https://orcid.org/0000-0001-7291-3210",
+            "@type": "Person",
+            "givenName": "Paolo",
+            "familyName": "Manghi",
+            "name": "Manghi, Paolo",
+            "identifier": [{
+                "@type": "PropertyValue",
+                "propertyID": "orcid",
+                "value": "0000-0001-7291-3210",
+            }],
+            "affiliation": [
+                {"@type": "Organization", "name": "OpenAIRE AMKE"},
+                {"@id": "https://ror.org/04zaypm56"},
+            ],
+        })
+    );
+
+    // ROR URIs are normalized to HTTPS, and IDs that can't be part of an IRI
+    // are encoded by name
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    for (ror_id, affiliation) in [
+        ("http://ror.org/04zaypm56", "<https://ror.org/04zaypm56>"),
+        ("04zaypm56> .", "_:orcid-0000-0001-7291-3210-affiliation-2"),
+        ("04zaypm 56", "_:orcid-0000-0001-7291-3210-affiliation-2"),
+    ] {
+        let mapping = dir.join("rdf-org-mapping.csv");
+        std::fs::write(&mapping, format!("RINGGOLD,9327,{}\n", ror_id))?;
+        let triples = stdout_lines(&[
+            "convert".as_ref(),
+            "--input-file".as_ref(),
+            "tests/data/paolo.xml".as_ref(),
+            "--format".as_ref(),
+            "ntriples".as_ref(),
+            "--orgs-mapping".as_ref(),
+            mapping.as_ref(),
+        ])?;
+        let affiliations: Vec<_> = triples
+            .iter()
+            .filter_map(|triple| {
+                triple.strip_prefix(
+                    "<https://orcid.org/0000-0001-7291-3210> <https://schema.org/affiliation> ",
+                )
+            })
+            .collect();
+        assert_eq!(affiliations[1], format!("{affiliation} ."));
+    }
+
+    // Empty name parts are left out
+    let no_family_name = |format: &str| {
+        stdout_lines(&[
+            "convert".as_ref(),
+            "--input-file".as_ref(),
+            "tests/data/no-family-name.xml".as_ref(),
+            "--format".as_ref(),
+            format.as_ref(),
+            "--mononym".as_ref(),
+            "given".as_ref(),
+        ])
+    };
+    let triples = no_family_name("ntriples")?;
+    assert!(triples.iter().any(|triple| triple.contains("/givenName>")));
+    assert!(!triples.iter().any(|triple| triple.contains("/familyName>")));
+    let person: serde_json::Value = serde_json::from_str(&no_family_name("jsonld")?[0])?;
+    assert!(person.get("givenName").is_some());
+    assert!(person.get("familyName").is_none());
+
+    // Records are streamed, with blank nodes that are unique across records
+    let input = build_tgz("convert-rdf", &FIXTURES)?;
+    let convert_tgz = |format: &str| {
+        stdout_lines(&[
+            "convert".as_ref(),
+            "--input-file".as_ref(),
+            input.as_ref(),
+            "--format".as_ref(),
+            format.as_ref(),
+        ])
+    };
+    assert_eq!(convert_tgz("jsonld")?.len(), FIXTURES.len());
+    let blank_nodes: BTreeSet<_> = convert_tgz("ntriples")?
+        .iter()
+        .filter(|triple| triple.starts_with("_:") && triple.contains("#type>"))
+        .map(|triple| triple.split(' ').next().unwrap().to_string())
+        .collect();
+    // One identifier per record, and the affiliations without a ROR ID
+    assert_eq!(blank_nodes.len(), FIXTURES.len() + 6);
+
+    Ok(())
+}
+
+#[test]
+fn extract_org_stats_xml() -> Result<()> {
+    let mut cmd = Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"));
+
+    let pred = concat!(
+        r#"{"scheme":"RINGGOLD","identifier":"30531","orcids":1,"current_employments":1,"past_employments":0,"names":[{"value":"European Organization for Nuclear Research","count":1}],"locations":[{"value":{"city":"Genève","region":"GE","country":"CH"},"count":1}]}"#,
+        "\n",
+        r#"{"scheme":"RINGGOLD","identifier":"54249","orcids":1,"current_employments":0,"past_employments":1,"names":[{"value":"European Southern Observatory","count":1}],"locations":[{"value":{"city":"Garching","region":"Bayern","country":"DE"},"count":1}]}"#,
+        "\n",
+    );
+    cmd.arg("extract")
+        .arg("--input-file")
+        .arg("tests/data/lars.xml")
+        .arg("--format")
+        .arg("org-stats")
+        .assert()
+        .success()
+        .stdout(pred);
+
+    Ok(())
+}
+
+#[test]
+fn extract_org_stats_tgz() -> Result<()> {
+    let input = build_tgz("extract-org-stats", &FIXTURES)?;
+
+    // Counts are aggregated over records, most referenced organizations first
+    let stats = extract(&input, &["--format", "org-stats"])?;
+    let cern: serde_json::Value = serde_json::from_str(&stats[0])?;
+    assert_eq!(
+        cern,
+        serde_json::json!({
+            "scheme": "RINGGOLD",
+            "identifier": "30531",
+            "orcids": 2,
+            "current_employments": 2,
+            "past_employments": 0,
+            "names": [{"value": "European Organization for Nuclear Research", "count": 2}],
+            "locations": [
+                {"value": {"city": "Geneve", "region": "GE", "country": "CH"}, "count": 1},
+                {"value": {"city": "Genève", "region": "GE", "country": "CH"}, "count": 1},
+            ],
+        })
+    );
+
+    // The CSV keeps the most common name/location, in the same order
+    let rows = extract(&input, &["--format", "org-stats-csv"])?;
+    assert_eq!(
+        rows[0],
+        "scheme,identifier,orcids,current_employments,past_employments,name,city,region,country"
+    );
+    assert_eq!(
+        rows[1],
+        "RINGGOLD,30531,2,2,0,European Organization for Nuclear Research,Geneve,GE,CH"
+    );
+    assert_eq!(rows.len(), stats.len() + 1);
+
+    Ok(())
+}
+
+#[test]
+fn extract_org_ids_external_dedup() -> Result<()> {
+    let input = build_tgz("extract-org-ids", &FIXTURES)?;
+    let in_memory = extract(&input, &[])?;
+    let external = extract_spilled(&input, "extract-org-ids")?;
+
+    let unique: BTreeSet<_> = in_memory.iter().cloned().collect();
+    assert_eq!(unique.len(), in_memory.len());
+    assert_eq!(external.len(), in_memory.len());
+    assert_eq!(external.iter().cloned().collect::<BTreeSet<_>>(), unique);
+
+    // Smaller budgets would write a run file per identifier
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args(["extract", "--dedup-memory", "0", "--input-file"])
+        .arg(&input)
+        .assert()
+        .code(2)
+        .stderr(predicates::str::contains("--dedup-memory <MB>"));
+
+    Ok(())
+}
+
+#[test]
+fn extract_org_ids_multi_pass_merge() -> Result<()> {
+    // With a zero budget every identifier is spilled into its own run, so
+    // many copies of the records need more runs than are merged at once
+    let small = [FIXTURES[0], FIXTURES[2], FIXTURES[3]];
+    let copies: Vec<_> = small
+        .iter()
+        .copied()
+        .cycle()
+        .take(small.len() * 8)
+        .collect();
+    let input = build_tgz("extract-org-ids-multi-pass", &copies)?;
+    let mut runs = 0;
+    for (fixture, _) in &small {
+        runs += 8 * extract(fixture.as_ref(), &[])?.len();
+    }
+    assert!(runs > 64, "{runs} runs");
+
+    let in_memory = extract(&input, &[])?;
+    let external = extract_spilled(&input, "extract-org-ids-multi-pass")?;
+
+    // Sorted by scheme and identifier, and unique
+    let ids = external
+        .iter()
+        .map(|line| {
+            let id: serde_json::Value = serde_json::from_str(line)?;
+            let field = |key: &str| id[key].as_str().unwrap_or_default().to_string();
+            Ok((field("scheme"), field("identifier")))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", ids);
+    assert_eq!(
+        external.iter().collect::<BTreeSet<_>>(),
+        in_memory.iter().collect::<BTreeSet<_>>()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn extract_person_ids_tgz() -> Result<()> {
+    let input = build_tgz("extract-person-ids", &FIXTURES)?;
+    let lines = extract(&input, &["--format", "person-ids"])?;
+
+    let expected = [
+        r#"{"scheme":"GitHub","identifier":"lnielsen"}"#,
+        r#"{"scheme":"Loop profile","identifier":"631013"}"#,
+        r#"{"scheme":"Public Key","identifier":"did:pkh:eip155:1:0xdce5503ffdcc973e09c4e85fab1d06b2d29230b5"}"#,
+        r#"{"scheme":"Scopus Author ID","identifier":"55655855500"}"#,
+        r#"{"scheme":"Scopus Author ID","identifier":"6602255248"}"#,
+    ];
+    assert_eq!(
+        lines.iter().map(String::as_str).collect::<BTreeSet<_>>(),
+        expected.into_iter().collect()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn extract_profile_formats_tgz() -> Result<()> {
+    let input = build_tgz("extract-profile-formats", &[FIXTURES[3], FIXTURES[5]])?;
+    let extract_set = |format: &str| -> Result<BTreeSet<String>> {
+        Ok(extract(&input, &["--format", format])?
+            .into_iter()
+            .collect())
+    };
+    let expected = |scheme: &str, identifiers: &[&str]| -> BTreeSet<String> {
+        identifiers
+            .iter()
+            .map(|i| format!(r#"{{"scheme":"{scheme}","identifier":"{i}"}}"#))
+            .collect()
+    };
+
+    assert_eq!(
+        extract_set("researcher-urls")?,
+        expected(
+            "url",
+            &["https://infrascience.isti.cnr.it/profile/paolo-manghi"]
+        )
+    );
+    assert_eq!(
+        extract_set("keywords")?,
+        expected(
+            "keyword",
+            &[
+                "data infrastructures",
+                "data",
+                "scholarly communication",
+                "service-oriented computing",
+                "FAIRness",
+                "services",
+                "computer science",
+            ]
+        )
+    );
+    assert_eq!(
+        extract_set("email-domains")?,
+        expected("email-domain", &["cern.ch", "isti.cnr.it", "openaire.eu"])
+    );
+    assert_eq!(
+        extract_set("countries")?,
+        expected("country", &["GR", "IT"])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn convert_person_ids() -> Result<()> {
+    let lines = stdout_lines(&[
+        "convert".as_ref(),
+        "--input-file".as_ref(),
+        "tests/data/duplicate-orgs.xml".as_ref(),
+        "--format".as_ref(),
+        "json".as_ref(),
+        "--person-id".as_ref(),
+        "Scopus Author ID".as_ref(),
+        "--person-id".as_ref(),
+        "ResearcherID=wos".as_ref(),
+    ])?;
+    let json: serde_json::Value = serde_json::from_str(&lines.join("\n"))?;
+
+    // "Loop profile" is not in the allowlist, and there is no ResearcherID
+    assert_eq!(
+        json["identifiers"],
+        serde_json::json!([
+            {"scheme": "orcid", "identifier": "0000-0001-7091-3022"},
+            {"scheme": "scopus", "identifier": "55655855500"},
+        ])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn convert_credit_and_other_names() -> Result<()> {
+    let json = convert(
+        "tests/data/lars.xml",
+        &["--use-credit-name", "--other-names"],
+    )?;
+    assert_eq!(json["name"], "Nielsen, L. H.");
+    assert_eq!(
+        json["alternative_names"],
+        serde_json::json!(["Nielsen, Lars Holm"])
+    );
+
+    let json = convert("tests/data/no-family-name.xml", &["--other-names"])?;
+    assert_eq!(json["name"], "Aleks Shtepa");
+    assert_eq!(json["alternative_names"].as_array().map(Vec::len), Some(1));
+
+    // Without the options the output is unchanged
+    let json = convert("tests/data/lars.xml", &[])?;
+    assert_eq!(json["name"], "Nielsen, Lars Holm");
+    assert!(json.get("alternative_names").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn convert_name_policy() -> Result<()> {
+    let args = [
+        "--name-template",
+        "{given} {family}",
+        "--name-case",
+        "upper",
+    ];
+    let json = convert("tests/data/lars.xml", &args)?;
+    assert_eq!(json["given_name"], "LARS HOLM");
+    assert_eq!(json["family_name"], "NIELSEN");
+    assert_eq!(json["name"], "LARS HOLM NIELSEN");
+
+    let json = convert("tests/data/no-family-name.xml", &["--mononym", "given"])?;
+    assert_eq!(json["given_name"], "Aleks Shtepa");
+    assert_eq!(json["family_name"], "");
+    assert_eq!(json["name"], "Aleks Shtepa");
+
+    // Placeholders within names are not substituted
+    let alex = std::fs::read_to_string("tests/data/alex.xml")?;
+    let fixture = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("placeholder-name.xml");
+    std::fs::write(&fixture, alex.replace(">Alex<", ">{family} Alex<"))?;
+    let json = convert(fixture.to_str().unwrap(), &["--filter-name", ".*"])?;
+    assert_eq!(json["name"], "Ioannidis, {family} Alex");
+
+    // A blank family name is not a mononym
+    let fixture = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("blank-family-name.xml");
+    std::fs::write(&fixture, alex.replace(">Ioannidis<", ">  <"))?;
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args(["convert", "--format", "json", "--input-file"])
+        .arg(&fixture)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Can't determine person name"));
+
+    Ok(())
+}
+
+#[test]
+fn convert_name_checks() -> Result<()> {
+    let input = build_tgz("convert-name-checks", &FIXTURES)?;
+    let review_file = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("name-checks-review.csv");
+    let convert = |mode: &str| {
+        stdout_lines(&[
+            "convert".as_ref(),
+            "--input-file".as_ref(),
+            input.as_ref(),
+            "--format".as_ref(),
+            "json".as_ref(),
+            // Uppercase all names, so that they get flagged as "all-caps"
+            "--name-case".as_ref(),
+            "upper".as_ref(),
+            "--name-checks".as_ref(),
+            mode.as_ref(),
+            "--review-file".as_ref(),
+            review_file.as_ref(),
+        ])
+    };
+
+    // Flagged records are kept in review mode...
+    assert_eq!(convert("review")?.len(), FIXTURES.len());
+    let review = std::fs::read_to_string(&review_file)?;
+    assert_eq!(review.lines().count(), FIXTURES.len() + 1);
+    assert!(review
+        .contains(r#"0000-0001-8135-3489,all-caps,false,LARS HOLM,NIELSEN,"NIELSEN, LARS HOLM""#));
+
+    // ...and left out in reject mode
+    assert!(convert("reject")?.is_empty());
+    let review = std::fs::read_to_string(&review_file)?;
+    assert!(review.contains("0000-0001-8135-3489,all-caps,true,"));
+
+    Ok(())
+}
+
+#[test]
+fn convert_name_check_rules() -> Result<()> {
+    let alex = std::fs::read_to_string("tests/data/alex.xml")?;
+    let fixture = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("name-check-rules.xml");
+    let review_file = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("name-check-rules.csv");
+    let check = |given: &str, family: &str| -> Result<Option<String>> {
+        let xml = alex
+            .replace(">Alex<", &format!(">{given}<"))
+            .replace(">Ioannidis<", &format!(">{family}<"));
+        std::fs::write(&fixture, xml)?;
+        let _ = std::fs::remove_file(&review_file);
+        let output = Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+            .args(["convert", "--format", "json", "--filter-name", ".*"])
+            .args(["--name-checks", "reject", "--input-file"])
+            .arg(&fixture)
+            .arg("--review-file")
+            .arg(&review_file)
+            .output()?;
+        if output.status.success() {
+            return Ok(None);
+        }
+        // Rejected records are written to the review file with the failed checks
+        let review = std::fs::read_to_string(&review_file)?;
+        let row: Vec<_> = review
+            .lines()
+            .nth(1)
+            .unwrap_or_default()
+            .split(',')
+            .collect();
+        assert_eq!(row[0], "0000-0002-5082-6404");
+        assert_eq!(row[2], "true");
+        Ok(Some(row[1].to_string()))
+    };
+
+    let rejected = [
+        ("alex@cern.ch", "Ioannidis", "all-lowercase;email"),
+        ("www.example.org", "Ioannidis", "all-lowercase;url"),
+        ("Alex2", "Ioannidis", "digits"),
+        ("Alex", "Ioannidis Ioannidis", "repeated-tokens"),
+        ("n/a", "Ioannidis", "placeholder"),
+        ("Ioannidis", "Ioannidis", "given-equals-family"),
+        ("alex", "Ioannidis", "all-lowercase"),
+        ("ALEX", "Ioannidis", "all-caps"),
+    ];
+    for (given, family, checks) in rejected {
+        assert_eq!(
+            check(given, family)?.as_deref(),
+            Some(checks),
+            "{given} {family}"
+        );
+    }
+    // Real surnames that are also common words are not placeholders
+    assert_eq!(check("Alex", "Na")?, None);
+    assert_eq!(check("Alex", "Name")?, None);
+
+    Ok(())
+}
+
+#[test]
+fn convert_scripts() -> Result<()> {
+    let args = ["--transliterate", "--annotate-scripts"];
+    let json = convert("tests/data/cyrillic-name.xml", &args)?;
+    assert_eq!(json["name"], "Штепа, Олексій");
+    assert_eq!(json["name_latin"], "Shtepa, Oleksii");
+    assert_eq!(json["scripts"], serde_json::json!(["Cyrillic"]));
+
+    // Latin names are not transliterated
+    let json = convert("tests/data/lars.xml", &args)?;
+    assert!(json.get("name_latin").is_none());
+    assert_eq!(json["scripts"], serde_json::json!(["Latin"]));
+
+    let input = build_tgz("convert-scripts", &FIXTURES)?;
+    let convert_tgz = |script: &str| {
+        stdout_lines(&[
+            "convert".as_ref(),
+            "--input-file".as_ref(),
+            input.as_ref(),
+            "--allow-script".as_ref(),
+            script.as_ref(),
+        ])
+    };
+    assert_eq!(convert_tgz("Latin")?.len(), FIXTURES.len());
+    assert!(convert_tgz("Cyrillic")?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn convert_name_filters() -> Result<()> {
+    let input = build_tgz("convert-name-filters", &FIXTURES)?;
+    let convert = |args: &[&OsStr]| {
+        let mut all_args = vec![
+            "convert".as_ref(),
+            "--input-file".as_ref(),
+            input.as_os_str(),
+        ];
+        all_args.extend(args);
+        stdout_lines(&all_args)
+    };
+
+    let patterns = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("exclude-names.txt");
+    std::fs::write(&patterns, "# Junk names\n\n^Manghi,\n")?;
+    let lines = convert(&[
+        "--exclude-name".as_ref(),
+        "Ioannidis".as_ref(),
+        "--exclude-names-file".as_ref(),
+        patterns.as_ref(),
+    ])?;
+    assert_eq!(lines.len(), FIXTURES.len() - 2);
+    assert!(!lines
+        .iter()
+        .any(|l| l.contains("Ioannidis") || l.contains("Manghi")));
+
+    // Invalid patterns are an error, for both .tar.gz and .xml inputs
+    for input_file in [input.as_os_str(), "tests/data/alex.xml".as_ref()] {
+        Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+            .args(["convert".as_ref(), "--input-file".as_ref(), input_file])
+            .args(["--filter-name", "("])
+            .assert()
+            .failure()
+            .stderr(predicates::str::contains("Invalid name filter"));
+    }
+
+    // The name filter also applies to .xml inputs, which then give no output
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args(["convert", "--input-file", "tests/data/alex.xml"])
+        .args(["--exclude-name", "^Ioannidis"])
+        .assert()
+        .success()
+        .stdout("");
+
+    Ok(())
+}
+
+#[test]
+fn convert_record_status_and_visibility() -> Result<()> {
+    // Only public elements are used
+    let json = convert("tests/data/limited-visibility.xml", &["--other-names"])?;
+    assert_eq!(
+        json["alternative_names"],
+        serde_json::json!(["M. Lindqvist"])
+    );
+    let keywords = extract(
+        "tests/data/limited-visibility.xml".as_ref(),
+        &["--format", "keywords"],
+    )?
+    .join("\n");
+    assert!(keywords.contains("Bioinformatics") && !keywords.contains("Secret project"));
+
+    // Inactive records are an error for single XML files...
+    for (fixture, state) in [
+        (
+            "tests/data/deprecated.xml",
+            "deprecated (see 0000-0002-5082-6404)",
+        ),
+        (
+            "tests/data/deprecated-error.xml",
+            "deprecated (see 0000-0002-0816-7126)",
+        ),
+        ("tests/data/locked.xml", "locked"),
+        ("tests/data/deactivated.xml", "deactivated"),
+    ] {
+        Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+            .args(["convert", "--input-file", fixture])
+            .assert()
+            .failure()
+            .stderr(predicates::str::contains(state));
+    }
+    // Their redirects are only written once a record is converted
+    let redirects = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("redirects.csv");
+    std::fs::write(&redirects, "previous redirects\n")?;
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args(["convert", "--input-file", "tests/data/deprecated.xml"])
+        .arg("--redirects-file")
+        .arg(&redirects)
+        .assert()
+        .failure();
+    assert_eq!(std::fs::read_to_string(&redirects)?, "previous redirects\n");
+
+    // ...and skipped in .tar.gz files, listing redirects of deprecated records
+    let mut fixtures = FIXTURES.to_vec();
+    fixtures.push(("tests/data/deprecated.xml", "0000-0002-1825-0097"));
+    fixtures.push(("tests/data/locked.xml", "0000-0001-5109-3700"));
+    fixtures.push(("tests/data/deactivated.xml", "0000-0003-1234-5674"));
+    fixtures.push(("tests/data/deprecated-error.xml", "0000-0002-9876-5436"));
+    let input = build_tgz("convert-record-status", &fixtures)?;
+    let lines = stdout_lines(&[
+        "convert".as_ref(),
+        "--input-file".as_ref(),
+        input.as_os_str(),
+        "--redirects-file".as_ref(),
+        redirects.as_os_str(),
+    ])?;
+    assert_eq!(lines.len(), FIXTURES.len());
+    assert_eq!(
+        std::fs::read_to_string(&redirects)?,
+        "orcid,primary_orcid\n0000-0002-1825-0097,0000-0002-5082-6404\n\
+         0000-0002-9876-5436,0000-0002-0816-7126\n"
+    );
+
+    // Errors are classified by their code, not by the wording of the messages
+    let locked = std::fs::read_to_string("tests/data/locked.xml")?.replace("locked", "closed");
+    let fixture = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("reworded-locked.xml");
+    std::fs::write(&fixture, locked)?;
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args(["convert", "--input-file"])
+        .arg(&fixture)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("is locked"));
+
+    Ok(())
+}
+
+#[test]
+fn select_orcids() -> Result<()> {
+    let input = build_tgz("select-orcids", &FIXTURES)?;
+    let only = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("only-orcids.txt");
+    std::fs::write(
+        &only,
+        "# Our people\n0000-0002-5082-6404\nhttps://orcid.org/0000-0001-8135-3489\n\n",
+    )?;
+    let exclude = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("exclude-orcids.txt");
+    std::fs::write(&exclude, "0000-0001-8135-3489\n")?;
+
+    let run = |command: &str, args: &[&OsStr]| {
+        let mut all_args = vec![command.as_ref(), "--input-file".as_ref(), input.as_os_str()];
+        all_args.extend(args);
+        stdout_lines(&all_args)
+    };
+    let lines = run("convert", &["--only-orcids".as_ref(), only.as_os_str()])?;
+    assert_eq!(lines.len(), 2);
+    let lines = run(
+        "convert",
+        &[
+            "--only-orcids".as_ref(),
+            only.as_os_str(),
+            "--exclude-orcids".as_ref(),
+            exclude.as_os_str(),
+        ],
+    )?;
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].contains("0000-0002-5082-6404"));
+
+    let all_ids = run("extract", &[])?;
+    let ids = run("extract", &["--exclude-orcids".as_ref(), only.as_os_str()])?;
+    assert!(!ids.is_empty() && ids.len() < all_ids.len());
+
+    // Unselected .xml inputs give no output, like unselected archive members
+    for command in ["convert", "extract"] {
+        Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+            .args([command, "--input-file", "tests/data/alex.xml"])
+            .arg("--exclude-orcids")
+            .arg(&only)
+            .assert()
+            .success()
+            .stdout("");
+    }
+
+    // Invalid iDs are an error
+    std::fs::write(&exclude, "0000-0001-8135\n")?;
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args([
+            "convert".as_ref(),
+            "--input-file".as_ref(),
+            input.as_os_str(),
+        ])
+        .args(["--exclude-orcids".as_ref(), exclude.as_os_str()])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Invalid ORCiD iD"));
+
+    Ok(())
+}
+
+#[test]
+fn convert_affiliation_filters() -> Result<()> {
+    let input = build_tgz("convert-affiliation-filters", &FIXTURES)?;
+    let orcids = |args: &[&str]| -> Result<Vec<String>> {
+        let mut all_args = vec![
+            "convert".as_ref(),
+            "--input-file".as_ref(),
+            input.as_os_str(),
+            "--format".as_ref(),
+            "json".as_ref(),
+        ];
+        all_args.extend(args.iter().map(OsStr::new));
+        let lines = stdout_lines(&all_args)?;
+        lines
+            .iter()
+            .map(|l| {
+                let json: serde_json::Value = serde_json::from_str(l)?;
+                Ok(json["identifiers"][0]["identifier"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string())
+            })
+            .collect()
+    };
+
+    assert_eq!(orcids(&["--no-affiliation"])?, ["0000-0003-0500-3000"]);
+    assert_eq!(orcids(&["--any-affiliation"])?.len(), FIXTURES.len() - 1);
+    // ROR IDs are matched after resolving them via the mappings file
+    assert_eq!(
+        orcids(&["--affiliation-ror", "https://ror.org/01ggx4157"])?,
+        ["0000-0002-5082-6404"]
+    );
+    assert_eq!(
+        orcids(&[
+            "--affiliation-ror",
+            "01ggx4157",
+            "--orgs-mapping",
+            "tests/data/org-mapping.csv"
+        ])?,
+        [
+            "0000-0002-5082-6404",
+            "0000-0002-0816-7126",
+            "0000-0001-8135-3489"
+        ]
+    );
+    assert_eq!(orcids(&["--affiliation-country", "ch"])?.len(), 3);
+    // Only the past employments are in Germany
+    assert!(orcids(&["--affiliation-country", "DE"])?.is_empty());
+    assert_eq!(
+        orcids(&["--affiliation-country", "DE", "--include-past-employments"])?,
+        ["0000-0001-8135-3489"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn index_and_get() -> Result<()> {
+    let input = build_tgz("index-and-get", &FIXTURES)?;
+    let index = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("index-and-get.idx");
+    // A span of 0 adds a checkpoint at every deflate block boundary, 1 (MB)
+    // spreads the records across a few sections, and 16 keeps them in one
+    for span in ["0", "1", "16"] {
+        Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+            .args(["index".as_ref(), "--input-file".as_ref(), input.as_os_str()])
+            .args(["--index-file".as_ref(), index.as_os_str()])
+            .args(["--span", span])
+            .assert()
+            .success()
+            .stderr(predicates::str::contains(format!(
+                "Indexed {} records",
+                FIXTURES.len()
+            )));
+
+        let get = |orcid: &str, format: &str| {
+            stdout_lines(&[
+                "get".as_ref(),
+                orcid.as_ref(),
+                "--input-file".as_ref(),
+                input.as_os_str(),
+                "--index-file".as_ref(),
+                index.as_os_str(),
+                "--format".as_ref(),
+                format.as_ref(),
+            ])
+        };
+        for (fixture, orcid) in FIXTURES {
+            let xml = std::fs::read_to_string(fixture)?;
+            assert_eq!(get(orcid, "xml")?, xml.lines().collect::<Vec<_>>());
+        }
+        let json: serde_json::Value =
+            serde_json::from_str(&get("0000-0002-5082-6404", "json")?.join("\n"))?;
+        assert_eq!(json["name"], "Ioannidis, Alex");
+    }
+
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args(["get", "0000-0000-0000-0000", "--input-file"])
+        .arg(&input)
+        .arg("--index-file")
+        .arg(&index)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("not found"));
+
+    // The name filter applies to `get --format json` as to `convert`
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args([
+            "get",
+            "0000-0002-5082-6404",
+            "--format",
+            "json",
+            "--filter-name",
+            "^M",
+        ])
+        .arg("--input-file")
+        .arg(&input)
+        .arg("--index-file")
+        .arg(&index)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("filtered out"));
+
+    // Options that only apply to whole conversions are rejected
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args([
+            "get",
+            "0000-0002-5082-6404",
+            "--sort-by",
+            "orcid",
+            "--input-file",
+        ])
+        .arg(&input)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("unexpected argument '--sort-by'"));
+
+    // Single records can't be read in parallel
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args([
+            "convert",
+            "--input-file",
+            "tests/data/alex.xml",
+            "--index-file",
+        ])
+        .arg(&index)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "only supported for .tar.gz input",
+        ));
+
+    // Corrupt indexes are rejected instead of read out of bounds
+    let index_data = std::fs::read(&index)?;
+    let corrupt = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("index-and-get-corrupt.idx");
+    let mut huge_count = index_data.clone();
+    // Checkpoint count, after the magic and four other header values
+    huge_count[40..48].copy_from_slice(&u64::MAX.to_le_bytes());
+    for data in [&index_data[..index_data.len() - 1], &huge_count] {
+        std::fs::write(&corrupt, data)?;
+        Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+            .args(["get", "0000-0002-5082-6404", "--input-file"])
+            .arg(&input)
+            .arg("--index-file")
+            .arg(&corrupt)
+            .assert()
+            .code(3)
+            .stderr(predicates::str::contains("Invalid index file"));
+    }
+
+    // Rewriting the archive, even with the same size, makes the index stale
+    let modified = std::fs::metadata(&input)?.modified()?;
+    std::fs::File::options()
+        .write(true)
+        .open(&input)?
+        .set_modified(modified + std::time::Duration::from_secs(1))?;
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args(["get", "0000-0002-5082-6404", "--input-file"])
+        .arg(&input)
+        .arg("--index-file")
+        .arg(&index)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "rebuild it with the `index` command",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn convert_indexed() -> Result<()> {
+    let input = build_tgz("convert-indexed", &FIXTURES)?;
+    let index = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("convert-indexed.idx");
+    let convert = |args: &[&OsStr]| {
+        let mut all_args = vec![
+            "convert".as_ref(),
+            "--input-file".as_ref(),
+            input.as_os_str(),
+            "--format".as_ref(),
+            "json".as_ref(),
+        ];
+        all_args.extend(args);
+        stdout_lines(&all_args)
+    };
+    let lines = convert(&[])?;
+    assert_eq!(lines.len(), FIXTURES.len());
+
+    // Sections are decompressed in parallel, but output in archive order,
+    // whether each holds a record or so (span 0) or several (span 1)
+    for span in ["0", "1"] {
+        Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+            .args(["index".as_ref(), "--input-file".as_ref(), input.as_os_str()])
+            .args(["--index-file".as_ref(), index.as_os_str()])
+            .args(["--span", span])
+            .assert()
+            .success();
+        assert_eq!(
+            convert(&["--index-file".as_ref(), index.as_os_str()])?,
+            lines
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn convert_sorted_and_reproducible() -> Result<()> {
+    let input = build_tgz("convert-sorted", &FIXTURES)?;
+    let convert = |args: &[&str]| {
+        let mut all_args = vec![
+            "convert".as_ref(),
+            "--input-file".as_ref(),
+            input.as_os_str(),
+        ];
+        all_args.extend(args.iter().map(OsStr::new));
+        stdout_lines(&all_args)
+    };
+
+    let lines = convert(&["--format", "json", "--sort-by", "orcid"])?;
+    let orcids: Vec<String> = lines
+        .iter()
+        .map(|line| {
+            let json: serde_json::Value = serde_json::from_str(line).unwrap();
+            json["identifiers"][0]["identifier"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        })
+        .collect();
+    let mut expected: Vec<String> = FIXTURES.iter().map(|(_, o)| o.to_string()).collect();
+    expected.sort();
+    assert_eq!(orcids, expected);
+    // Sorting on disk gives the same output
+    let args = [
+        "--format",
+        "json",
+        "--sort-by",
+        "orcid",
+        "--sort-memory",
+        "0",
+    ];
+    assert_eq!(convert(&args)?, lines);
+
+    // With a fixed timestamp, ids and timestamps are the same in every run
+    let args = ["--timestamp", "2024-10-01T00:00:00Z", "--sort-by", "orcid"];
+    let rows = convert(&args)?;
+    assert_eq!(rows.len(), FIXTURES.len());
+    assert!(rows[0].starts_with("2024-10-01T00:00:00+00:00,2024-10-01T00:00:00+00:00,"));
+    assert_eq!(convert(&args)?, rows);
+
+    Ok(())
+}
+
+#[test]
+fn convert_sharded() -> Result<()> {
+    let input = build_tgz("convert-sharded", &FIXTURES)?;
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("convert-sharded");
+    std::fs::create_dir_all(&dir)?;
+    let convert = |args: &[&str]| {
+        let mut all_args = vec![
+            "convert".as_ref(),
+            "--input-file".as_ref(),
+            input.as_os_str(),
+        ];
+        all_args.extend(["--format", "json"].map(OsStr::new));
+        all_args.extend(args.iter().map(OsStr::new));
+        stdout_lines(&all_args)
+    };
+    let lines: BTreeSet<String> = convert(&[])?.into_iter().collect();
+    let read_manifest = |name: &str| -> Result<(serde_json::Value, BTreeSet<String>)> {
+        let manifest: serde_json::Value =
+            serde_json::from_reader(File::open(dir.join(format!("{name}.manifest.json")))?)?;
+        let mut shard_lines = BTreeSet::new();
+        for shard in manifest["shards"].as_array().unwrap() {
+            let content = std::fs::read_to_string(dir.join(shard["file"].as_str().unwrap()))?;
+            assert_eq!(
+                content.lines().count() as u64,
+                shard["records"].as_u64().unwrap()
+            );
+            let sha256 = format!("{:x}", Sha256::digest(content.as_bytes()));
+            assert_eq!(shard["sha256"], sha256);
+            shard_lines.extend(content.lines().map(String::from));
+        }
+        Ok((manifest, shard_lines))
+    };
+
+    // By count
+    let output = dir.join("by-count.ndjson");
+    convert(&[
+        "--shard-size",
+        "4",
+        "--output-file",
+        output.to_str().unwrap(),
+    ])?;
+    let (manifest, shard_lines) = read_manifest("by-count")?;
+    assert!(!output.exists());
+    assert_eq!(manifest["sharding"], "count");
+    assert_eq!(manifest["records"], 6);
+    assert_eq!(manifest["shards"][0]["file"], "by-count-00001.ndjson");
+    assert_eq!(manifest["shards"][0]["records"], 4);
+    assert_eq!(manifest["shards"][1]["records"], 2);
+    assert_eq!(shard_lines, lines);
+
+    // By hash of the ORCiD iD
+    let output = dir.join("by-hash.ndjson");
+    convert(&["--shards", "3", "--output-file", output.to_str().unwrap()])?;
+    let (manifest, shard_lines) = read_manifest("by-hash")?;
+    assert_eq!(manifest["sharding"], "hash");
+    assert_eq!(manifest["records"], 6);
+    assert_eq!(manifest["shards"].as_array().unwrap().len(), 3);
+    assert_eq!(shard_lines, lines);
+
+    // All shards are kept open, so there can't be too many of them
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args([
+            "convert".as_ref(),
+            "--input-file".as_ref(),
+            input.as_os_str(),
+        ])
+        .args(["--shards", "257", "--output-file"])
+        .arg(&output)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("257 is not in 1..=256"));
+
+    // Single XML files are neither sharded nor checkpointed
+    for args in [
+        &["--shards", "3"][..],
+        &["--shard-size", "4"],
+        &["--resume"],
+    ] {
+        Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+            .args([
+                "convert",
+                "--input-file",
+                "tests/data/alex.xml",
+                "--output-file",
+            ])
+            .arg(dir.join("single.ndjson"))
+            .args(args)
+            .assert()
+            .failure()
+            .stderr(predicates::str::contains(
+                "only supported for .tar.gz input files",
+            ));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn convert_resume() -> Result<()> {
+    let input = build_tgz("convert-resume", &FIXTURES)?;
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("convert-resume");
+    std::fs::create_dir_all(&dir)?;
+    let index = dir.join("convert-resume.idx");
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args(["index".as_ref(), "--input-file".as_ref(), input.as_os_str()])
+        .args(["--index-file".as_ref(), index.as_os_str()])
+        .args(["--span", "0"])
+        .assert()
+        .success();
+    let timestamp = "2024-10-01T00:00:00+00:00";
+    let convert = |output: &PathBuf, args: &[&OsStr]| {
+        Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+            .args([
+                "convert".as_ref(),
+                "--input-file".as_ref(),
+                input.as_os_str(),
+            ])
+            .args(["--output-file".as_ref(), output.as_os_str()])
+            .args(args)
+            .assert()
+            .success();
+        Ok::<_, anyhow::Error>(std::fs::read_to_string(output)?)
+    };
+    let output = dir.join("names.csv");
+    let expected = convert(&output, &["--timestamp".as_ref(), timestamp.as_ref()])?;
+    assert_eq!(expected.lines().count(), FIXTURES.len());
+
+    // A checkpoint of a conversion that failed to replace the output file
+    // (a directory), after converting all records
+    std::fs::remove_file(&output)?;
+    std::fs::create_dir(&output)?;
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args([
+            "convert".as_ref(),
+            "--input-file".as_ref(),
+            input.as_os_str(),
+        ])
+        .args(["--output-file".as_ref(), output.as_os_str()])
+        .args(["--checkpoint-interval", "0", "--timestamp", timestamp])
+        .assert()
+        .failure();
+    std::fs::remove_dir(&output)?;
+    let checkpoint_file = dir.join("names.csv.checkpoint");
+    let mut checkpoint: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&checkpoint_file)?)?;
+    assert_eq!(checkpoint["format"], "invenio-rdm-names");
+    assert_eq!(checkpoint["created"], timestamp);
+
+    // Position of the header of the third record in the decompressed archive
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(File::open(&input)?));
+    let third = archive
+        .entries()?
+        .filter(|entry| {
+            let entry = entry.as_ref().unwrap();
+            entry.header().entry_type() != tar::EntryType::Directory
+        })
+        .nth(2)
+        .unwrap()?;
+    let position = third.raw_header_position();
+    let member = third.path()?.display().to_string();
+
+    // Simulate a conversion that was interrupted after the third member,
+    // while writing the fourth one (to the temporary output file)
+    let offset: usize = expected.lines().take(3).map(|l| l.len() + 1).sum();
+    checkpoint["last_member"] = serde_json::json!({"path": member, "position": position});
+    checkpoint["output_offset"] = offset.into();
+    let partial = dir.join("names.csv.tmp");
+    let interrupt = || {
+        std::fs::write(&partial, format!("{}partial line", &expected[..offset]))?;
+        std::fs::write(&checkpoint_file, checkpoint.to_string())
+    };
+
+    // Conversions are only resumed with the same format and options
+    for (args, error) in [
+        (
+            &["--format", "json"][..],
+            "is for a different output format",
+        ),
+        (
+            &["--other-names"],
+            "is for a conversion with different options",
+        ),
+    ] {
+        interrupt()?;
+        Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+            .args([
+                "convert".as_ref(),
+                "--input-file".as_ref(),
+                input.as_os_str(),
+            ])
+            .args(["--output-file".as_ref(), output.as_os_str()])
+            .arg("--resume")
+            .args(args)
+            .assert()
+            .failure()
+            .stderr(predicates::str::contains(error));
+        assert!(checkpoint_file.exists());
+    }
+
+    for args in [vec![], vec!["--index-file".as_ref(), index.as_os_str()]] {
+        interrupt()?;
+        let mut resume_args = vec!["--resume".as_ref()];
+        resume_args.extend(&args);
+        assert_eq!(convert(&output, &resume_args)?, expected);
+        assert!(!checkpoint_file.exists());
+        assert!(!partial.exists());
+        std::fs::remove_file(&output)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn library_reader_and_converter() -> Result<()> {
+    use orcid_data_toolkit::{ConvertOptions, NameConverter, RecordReader};
+
+    let input = build_tgz("library", &FIXTURES)?;
+    let converter = NameConverter::new(&ConvertOptions::default())?;
+    let mut orcids = vec![];
+    let mut names = vec![];
+    for record in RecordReader::open(&input)? {
+        let record = record?;
+        orcids.push(record.identifier.path.to_string());
+        if let Some(name) = converter.convert(&record)? {
+            names.push(serde_json::to_string(&name)?);
+        }
+    }
+    let expected: Vec<_> = FIXTURES
+        .iter()
+        .map(|(_, orcid)| orcid.to_string())
+        .collect();
+    assert_eq!(orcids, expected);
+
+    // Same names as the `convert` command
+    let args = [
+        "convert",
+        "--input-file",
+        input.to_str().unwrap(),
+        "--format",
+        "json",
+    ];
+    let args: Vec<&OsStr> = args.iter().map(OsStr::new).collect();
+    assert_eq!(names, stdout_lines(&args)?);
+
+    // Directories are read recursively, with invalid records as errors
+    let results: Vec<_> = RecordReader::open("tests/data")?.collect();
+    assert!(results.iter().any(|r| r.is_err()));
+    assert!(results
+        .iter()
+        .flatten()
+        .any(|r| r.identifier.path == "0000-0002-5082-6404"));
+    assert!(RecordReader::open("tests/data/org-mapping.csv").is_err());
+    Ok(())
+}
+
+#[test]
+fn exit_codes() -> Result<()> {
+    let run = |args: &[&OsStr]| {
+        Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+            .args(args)
+            .output()
+    };
+    let tmp = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    let missing = tmp.join("missing.xml");
+    let invalid = "tests/data/errors/missing-name.xml";
+    let unwritable = tmp.join("missing-dir").join("names.csv");
+
+    // Input, parse and output failures
+    let output = run(&["convert".as_ref(), "-i".as_ref(), missing.as_os_str()])?;
+    assert_eq!(output.status.code(), Some(3), "{:?}", output);
+    let output = run(&["convert".as_ref(), "-i".as_ref(), invalid.as_ref()])?;
+    assert_eq!(output.status.code(), Some(4), "{:?}", output);
+    let output = run(&[
+        "convert".as_ref(),
+        "-i".as_ref(),
+        "tests/data/alex.xml".as_ref(),
+        "-o".as_ref(),
+        unwritable.as_os_str(),
+    ])?;
+    assert_eq!(output.status.code(), Some(5), "{:?}", output);
+
+    // A truncated archive is an error instead of a partial conversion
+    let input = build_tgz("exit-codes", &FIXTURES)?;
+    let truncated = tmp.join("exit-codes-truncated.tar.gz");
+    let data = std::fs::read(&input)?;
+    std::fs::write(&truncated, &data[..data.len() / 2])?;
+    for command in ["convert", "extract"] {
+        let output = run(&[command.as_ref(), "-i".as_ref(), truncated.as_os_str()])?;
+        assert_eq!(output.status.code(), Some(3), "{:?}", output);
+        let stderr = String::from_utf8(output.stderr)?;
+        assert!(!stderr.contains("panicked"), "{}", stderr);
+    }
+    Ok(())
+}
+
+#[test]
+fn atomic_output() -> Result<()> {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("atomic-output");
+    std::fs::create_dir_all(&dir)?;
+    let output = dir.join("names.csv");
+    std::fs::write(&output, "previous names\n")?;
+    let redirects = dir.join("redirects.csv");
+    std::fs::write(&redirects, "previous redirects\n")?;
+
+    // A failed run leaves the previous output in place, and no temporary file
+    let input = build_tgz("atomic-output", &FIXTURES)?;
+    let truncated = dir.join("truncated.tar.gz");
+    let data = std::fs::read(&input)?;
+    std::fs::write(&truncated, &data[..data.len() / 2])?;
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args(["convert".as_ref(), "-i".as_ref(), truncated.as_os_str()])
+        .args(["-o".as_ref(), output.as_os_str()])
+        .args(["--redirects-file".as_ref(), redirects.as_os_str()])
+        .assert()
+        .failure();
+    assert_eq!(std::fs::read_to_string(&output)?, "previous names\n");
+    assert_eq!(std::fs::read_to_string(&redirects)?, "previous redirects\n");
+    assert!(!dir.join("names.csv.tmp").exists());
+    assert!(!dir.join("redirects.csv.tmp").exists());
+
+    // A successful one replaces it
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args(["convert".as_ref(), "-i".as_ref(), input.as_os_str()])
+        .args(["-o".as_ref(), output.as_os_str()])
+        .args(["--redirects-file".as_ref(), redirects.as_os_str()])
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(&output)?.lines().count(),
+        FIXTURES.len()
+    );
+    assert_eq!(std::fs::read_to_string(&redirects)?, "");
+    assert!(!dir.join("names.csv.tmp").exists());
+    assert!(!dir.join("redirects.csv.tmp").exists());
+
+    // Errors name the output file
+    let unwritable = dir.join("missing-dir").join("names.csv");
+    Command::new(assert_cmd::cargo::cargo_bin!("orcid-data-toolkit"))
+        .args(["convert".as_ref(), "-i".as_ref(), input.as_os_str()])
+        .args(["-o".as_ref(), unwritable.as_os_str()])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(format!(
+            "Error opening file {} (temporary file {}.tmp)",
+            unwritable.display(),
+            unwritable.display()
+        )));
+    Ok(())
+}
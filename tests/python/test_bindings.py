@@ -0,0 +1,92 @@
+"""Tests of the Python bindings, run with `maturin develop && pytest tests/python`."""
+
+import json
+from pathlib import Path
+
+import pytest
+
+import orcid_data_toolkit as odt
+
+DATA = Path(__file__).parent.parent / "data"
+
+
+def test_convert_record():
+    mappings = odt.OrgMappings.from_csv(DATA / "org-mapping.csv")
+    converter = odt.NameConverter(orgs_mapping=mappings)
+    name = converter.convert((DATA / "alex.xml").read_text())
+    assert name["name"] == "Ioannidis, Alex"
+    assert name["identifiers"] == [
+        {"scheme": "orcid", "identifier": "0000-0002-5082-6404"}
+    ]
+    assert name["affiliations"][0]["id"] == "01ggx4157"
+
+    # Inactive records and filtered out names aren't converted
+    assert converter.convert((DATA / "deprecated.xml").read_text()) is None
+    converter = odt.NameConverter(exclude_names=["^Ioannidis"])
+    assert converter.convert((DATA / "alex.xml").read_text()) is None
+    assert converter.convert((DATA / "no-name.xml").read_text()) is None
+    with pytest.raises(ValueError):
+        converter.convert("<record/>")
+
+
+def test_name_reader():
+    converter = odt.NameConverter(exclude_names=["^Manghi"])
+    names = [
+        name["name"]
+        for name in odt.NameReader(DATA / "summaries.tar.gz", converter=converter)
+    ]
+    assert names == ["Ioannidis, Alex"]
+
+
+def test_converter_options():
+    converter = odt.NameConverter(other_names=True, mononym="given", filter_name=None)
+    name = converter.convert((DATA / "no-family-name.xml").read_text())
+    assert name["family_name"] == ""
+    assert name["alternative_names"]
+
+    with pytest.raises(ValueError):
+        odt.NameConverter(mononym="middle")
+    with pytest.raises(ValueError):
+        odt.NameConverter(affiliation_countries=["Switzerland"])
+    with pytest.raises(TypeError):
+        odt.NameConverter(options=["--other-names"])
+
+
+def test_name_reader_parse_errors(tmp_path):
+    (tmp_path / "alex.xml").write_text((DATA / "alex.xml").read_text())
+    (tmp_path / "invalid.xml").write_text("<record/>")
+    with pytest.warns(RuntimeWarning, match="invalid.xml"):
+        names = [name["name"] for name in odt.NameReader(tmp_path)]
+    assert names == ["Ioannidis, Alex"]
+
+
+def test_convert(tmp_path):
+    output = tmp_path / "alex.json"
+    odt.convert(DATA / "alex.xml", output, "json")
+    assert json.loads(output.read_text())["family_name"] == "Ioannidis"
+
+    with pytest.raises(OSError):
+        odt.convert(tmp_path / "missing.tar.gz", tmp_path / "names.csv")
+
+    output = tmp_path / "names.csv"
+    odt.convert(DATA / "summaries.tar.gz", output, sort_by="orcid", shard_size=1)
+    assert len(list(tmp_path.glob("names-*.csv"))) == 2
+    with pytest.raises(ValueError):
+        odt.convert(DATA / "summaries.tar.gz", output, shards=0)
+
+
+def test_org_mappings(tmp_path):
+    mappings = odt.OrgMappings()
+    mappings.add("RINGGOLD", "30531", "01ggx4157")
+    mappings.add("GRID", "grid.9132.9", "01ggx4157")
+    assert len(mappings) == 2
+    assert mappings.get("GRID", "grid.9132.9") == "01ggx4157"
+    assert mappings.get("GRID", "grid.1.1") is None
+
+    path = tmp_path / "org-mapping.csv"
+    mappings.to_csv(path)
+    assert path.read_text().splitlines() == [
+        "GRID,grid.9132.9,01ggx4157",
+        "RINGGOLD,30531,01ggx4157",
+    ]
+    assert len(odt.OrgMappings.from_csv(path)) == 2